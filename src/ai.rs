@@ -0,0 +1,240 @@
+use crate::board_helpers::all_legal_moves;
+use dexterws_chess::game::{Board, Color as PieceColor, GameResult as ChessResult, Move, Piece};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const MIN_DEPTH: u32 = 1;
+pub const MAX_DEPTH: u32 = 6;
+// the search stops deepening once this much time has been spent
+pub const TIME_LIMIT: Duration = Duration::from_secs(3);
+
+const MATE_SCORE: i32 = 1_000_000;
+
+// piece-square tables from white's point of view, the first row is the eighth rank
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+pub fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 320,
+        Piece::Bishop => 330,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 20_000,
+    }
+}
+
+fn piece_square_value(piece: Piece, color: PieceColor, index: usize) -> i32 {
+    let (file, rank) = (index % 8, index / 8);
+    // the tables are written with the eighth rank first, black reads them mirrored
+    let table_index = match color {
+        PieceColor::White => (7 - rank) * 8 + file,
+        PieceColor::Black => rank * 8 + file,
+    };
+    let table = match piece {
+        Piece::Pawn => &PAWN_TABLE,
+        Piece::Knight => &KNIGHT_TABLE,
+        Piece::Bishop => &BISHOP_TABLE,
+        Piece::Rook => &ROOK_TABLE,
+        Piece::Queen => &QUEEN_TABLE,
+        Piece::King => &KING_TABLE,
+    };
+    table[table_index]
+}
+
+// material and piece placement, positive when the side to move is better
+pub fn evaluate(board: &Board) -> i32 {
+    let side = board.side();
+    board
+        .get_all_pieces()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, piece)| piece.map(|p| (index, p)))
+        .map(|(index, (piece, color))| {
+            let value = piece_value(piece) + piece_square_value(piece, color, index);
+            if color == side {
+                value
+            } else {
+                -value
+            }
+        })
+        .sum()
+}
+
+// captures of valuable pieces are searched first so alpha-beta can cut more
+fn order_moves(board: &Board, moves: &mut [Move]) {
+    let pieces = board.get_all_pieces();
+    moves.sort_by_cached_key(|m| {
+        let to = (m.to().rank.to_idx() * 8 + m.to().file.to_idx()) as usize;
+        let captured = pieces[to].map(|(piece, _)| piece_value(piece)).unwrap_or(0);
+        let promotion = m.promotion().map(piece_value).unwrap_or(0);
+        -(captured + promotion)
+    });
+}
+
+struct Search {
+    deadline: Instant,
+    timed_out: bool,
+}
+
+impl Search {
+    // negamax with alpha-beta pruning, None when the deadline has passed
+    fn alpha_beta(
+        &mut self,
+        board: &Board,
+        depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> Option<i32> {
+        if Instant::now() >= self.deadline {
+            self.timed_out = true;
+            return None;
+        }
+        match board.get_game_result() {
+            // the side to move has been mated, sooner mates score worse
+            ChessResult::Checkmate { .. } => return Some(-MATE_SCORE + ply),
+            ChessResult::InProgress => {}
+            _ => return Some(0),
+        }
+        if depth == 0 {
+            return Some(evaluate(board));
+        }
+
+        let mut moves = all_legal_moves(board);
+        order_moves(board, &mut moves);
+        for chess_move in moves {
+            let mut next = board.clone();
+            if next.make_move(chess_move).is_err() {
+                continue;
+            }
+            let score = -self.alpha_beta(&next, depth - 1, ply + 1, -beta, -alpha)?;
+            if score >= beta {
+                return Some(beta);
+            }
+            alpha = alpha.max(score);
+        }
+        Some(alpha)
+    }
+
+    fn best_move(&mut self, board: &Board, depth: u32, moves: &[Move]) -> Option<Move> {
+        let mut best: Option<(Move, i32)> = None;
+        let mut alpha = -MATE_SCORE - 1;
+        for chess_move in moves {
+            let mut next = board.clone();
+            if next.make_move(*chess_move).is_err() {
+                continue;
+            }
+            let score = -self.alpha_beta(&next, depth - 1, 1, -MATE_SCORE - 1, -alpha)?;
+            if best.is_none() || score > alpha {
+                alpha = score;
+                best = Some((*chess_move, score));
+            }
+        }
+        best.map(|(chess_move, _)| chess_move)
+    }
+}
+
+// iterative deepening up to max_depth, keeping the result of the deepest search that finished
+pub fn find_best_move(board: &Board, max_depth: u32, time_limit: Duration) -> Option<Move> {
+    let mut search = Search {
+        deadline: Instant::now() + time_limit,
+        timed_out: false,
+    };
+    let mut moves = all_legal_moves(board);
+    order_moves(board, &mut moves);
+    let mut best = moves.first().copied();
+    for depth in MIN_DEPTH..=max_depth.max(MIN_DEPTH) {
+        match search.best_move(board, depth, &moves) {
+            Some(chess_move) => {
+                // search the previous best move first at the next depth
+                if let Some(index) = moves.iter().position(|m| *m == chess_move) {
+                    moves[..=index].rotate_right(1);
+                }
+                best = Some(chess_move);
+            }
+            None => break,
+        }
+        if search.timed_out {
+            break;
+        }
+    }
+    best
+}
+
+// runs the search off the ui thread, the move arrives on the returned channel
+pub fn spawn_search(board: Board, max_depth: u32) -> Receiver<Option<Move>> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let best_move = find_best_move(&board, max_depth, TIME_LIMIT);
+        // the game may have been restarted while searching, then no one is listening
+        let _ = sender.send(best_move);
+    });
+    receiver
+}
@@ -0,0 +1,139 @@
+use dexterws_chess::game::{Board, Color as PieceColor, Move, Piece, Square};
+
+pub fn opposite_color(color: PieceColor) -> PieceColor {
+    match color {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    }
+}
+
+// every legal move for the side to move
+pub fn all_legal_moves(board: &Board) -> Vec<Move> {
+    let side = board.side();
+    board
+        .get_all_pieces()
+        .iter()
+        .enumerate()
+        .filter(|(_, piece)| piece.is_some_and(|(_, color)| color == side))
+        .flat_map(|(index, _)| {
+            board
+                .get_moves(Square::from_idx(index as u8))
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn square_index(file: i8, rank: i8) -> Option<usize> {
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as usize)
+    } else {
+        None
+    }
+}
+
+// whether a piece of color `by` could capture on the square at `index`
+pub fn is_square_attacked(
+    pieces: &[Option<(Piece, PieceColor)>; 64],
+    index: usize,
+    by: PieceColor,
+) -> bool {
+    let (file, rank) = ((index % 8) as i8, (index / 8) as i8);
+    let has = |offset: (i8, i8), wanted: &[Piece]| {
+        square_index(file + offset.0, rank + offset.1)
+            .and_then(|i| pieces[i])
+            .is_some_and(|(piece, color)| color == by && wanted.contains(&piece))
+    };
+
+    // a white pawn attacks upwards, so it stands one rank below the square it attacks
+    let pawn_rank = if by == PieceColor::White { -1 } else { 1 };
+    if has((-1, pawn_rank), &[Piece::Pawn]) || has((1, pawn_rank), &[Piece::Pawn]) {
+        return true;
+    }
+    const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+        (1, 2),
+        (2, 1),
+        (2, -1),
+        (1, -2),
+        (-1, -2),
+        (-2, -1),
+        (-2, 1),
+        (-1, 2),
+    ];
+    if KNIGHT_OFFSETS
+        .iter()
+        .any(|offset| has(*offset, &[Piece::Knight]))
+    {
+        return true;
+    }
+    const KING_OFFSETS: [(i8, i8); 8] = [
+        (1, 0),
+        (1, 1),
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+    ];
+    if KING_OFFSETS
+        .iter()
+        .any(|offset| has(*offset, &[Piece::King]))
+    {
+        return true;
+    }
+
+    // sliding pieces, walking outwards until the first piece in each direction
+    for (direction, sliders) in [
+        ((1, 0), [Piece::Rook, Piece::Queen]),
+        ((-1, 0), [Piece::Rook, Piece::Queen]),
+        ((0, 1), [Piece::Rook, Piece::Queen]),
+        ((0, -1), [Piece::Rook, Piece::Queen]),
+        ((1, 1), [Piece::Bishop, Piece::Queen]),
+        ((1, -1), [Piece::Bishop, Piece::Queen]),
+        ((-1, 1), [Piece::Bishop, Piece::Queen]),
+        ((-1, -1), [Piece::Bishop, Piece::Queen]),
+    ] {
+        let (mut f, mut r) = (file + direction.0, rank + direction.1);
+        while let Some(i) = square_index(f, r) {
+            if let Some((piece, color)) = pieces[i] {
+                if color == by && sliders.contains(&piece) {
+                    return true;
+                }
+                break;
+            }
+            f += direction.0;
+            r += direction.1;
+        }
+    }
+    false
+}
+
+pub fn king_square(board: &Board, color: PieceColor) -> Option<Square> {
+    board
+        .get_all_pieces()
+        .iter()
+        .position(|piece| *piece == Some((Piece::King, color)))
+        .map(|index| Square::from_idx(index as u8))
+}
+
+// whether the side to move is in check
+pub fn is_in_check(board: &Board) -> bool {
+    let side = board.side();
+    let pieces = board.get_all_pieces();
+    pieces
+        .iter()
+        .position(|piece| *piece == Some((Piece::King, side)))
+        .is_some_and(|index| is_square_attacked(&pieces, index, opposite_color(side)))
+}
+
+pub fn square_to_index(square: Square) -> usize {
+    (square.rank.to_idx() * 8 + square.file.to_idx()) as usize
+}
+
+pub fn square_name(square: Square) -> String {
+    format!(
+        "{}{}",
+        (b'a' + square.file.to_idx()) as char,
+        (b'1' + square.rank.to_idx()) as char
+    )
+}
@@ -0,0 +1,104 @@
+use dexterws_chess::game::Color as PieceColor;
+use std::time::{Duration, Instant};
+
+// (time in seconds, increment in seconds), None = untimed
+pub const TIME_CONTROLS: [(Option<(u64, u64)>, &str); 5] = [
+    (None, "Untimed"),
+    (Some((60, 0)), "1 + 0"),
+    (Some((180, 2)), "3 + 2"),
+    (Some((300, 0)), "5 + 0"),
+    (Some((600, 5)), "10 + 5"),
+];
+
+pub struct ChessClock {
+    white_remaining: Duration,
+    black_remaining: Duration,
+    increment: Duration,
+    active: PieceColor,
+    // when the active side's clock was last started, None while paused
+    running_since: Option<Instant>,
+}
+
+impl ChessClock {
+    pub fn new(time: u64, inc: u64, side_to_move: PieceColor) -> ChessClock {
+        ChessClock {
+            white_remaining: Duration::from_secs(time),
+            black_remaining: Duration::from_secs(time),
+            increment: Duration::from_secs(inc),
+            active: side_to_move,
+            running_since: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        if self.running_since.is_none() {
+            self.running_since = Some(Instant::now());
+        }
+    }
+
+    pub fn stop(&mut self) {
+        let remaining = self.remaining(self.active);
+        *self.remaining_mut(self.active) = remaining;
+        self.running_since = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running_since.is_some()
+    }
+
+    pub fn active(&self) -> PieceColor {
+        self.active
+    }
+
+    pub fn remaining(&self, color: PieceColor) -> Duration {
+        let stored = match color {
+            PieceColor::White => self.white_remaining,
+            PieceColor::Black => self.black_remaining,
+        };
+        match self.running_since {
+            Some(since) if color == self.active => stored.saturating_sub(since.elapsed()),
+            _ => stored,
+        }
+    }
+
+    // called once a move by `mover` has been played, gives the increment and hands over the clock
+    pub fn press(&mut self, mover: PieceColor) {
+        let was_running = self.is_running();
+        self.stop();
+        let increment = self.increment;
+        *self.remaining_mut(mover) += increment;
+        self.active = match mover {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        };
+        if was_running {
+            self.start();
+        }
+    }
+
+    // the side whose time has run out, if any
+    pub fn flagged(&self) -> Option<PieceColor> {
+        if self.remaining(self.active).is_zero() {
+            Some(self.active)
+        } else {
+            None
+        }
+    }
+
+    fn remaining_mut(&mut self, color: PieceColor) -> &mut Duration {
+        match color {
+            PieceColor::White => &mut self.white_remaining,
+            PieceColor::Black => &mut self.black_remaining,
+        }
+    }
+}
+
+// m:ss, with tenths of a second once below ten seconds
+pub fn format_clock(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs < 10 {
+        format!("0:{:02}.{}", secs, time.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
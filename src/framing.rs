@@ -0,0 +1,241 @@
+use chess_networking::{Ack, Move as NetworkMove, Start};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+// every frame on the wire is [length: u32 big endian][tag: u8][payload], where length counts the
+// tag and the payload, so a reader always knows where one message ends and the next begins
+const HEADER_LEN: usize = 4;
+const MOVE_TAG: u8 = 0;
+const ACK_TAG: u8 = 1;
+const START_TAG: u8 = 2;
+const HISTORY_TAG: u8 = 3;
+const PING_TAG: u8 = 4;
+const PONG_TAG: u8 = 5;
+const REJECTION_TAG: u8 = 6;
+// no packet comes close to this, a larger length means the stream is out of sync
+const MAX_FRAME_LEN: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum Packet {
+    Move(NetworkMove),
+    Ack(Ack),
+    Start(Start),
+    // the moves of a paused game in uci notation, oldest first, sent to a player rejoining it
+    History(Vec<String>),
+    // keepalive, the peer answers a ping with a pong carrying the same timestamp
+    Ping(u64),
+    Pong(u64),
+    // why the host refused the client's move, sent just before the ack that refuses it
+    Rejection(String),
+}
+
+pub fn encode_packet(packet: Packet) -> Result<Vec<u8>, String> {
+    let (tag, payload): (u8, Vec<u8>) = match packet {
+        Packet::Move(piece_move) => (
+            MOVE_TAG,
+            piece_move.try_into().map_err(|e| format!("{}", e))?,
+        ),
+        Packet::Ack(ack) => (ACK_TAG, ack.try_into().map_err(|e| format!("{}", e))?),
+        Packet::Start(start) => (START_TAG, start.try_into().map_err(|e| format!("{}", e))?),
+        Packet::History(moves) => (HISTORY_TAG, moves.join(" ").into_bytes()),
+        Packet::Ping(sent) => (PING_TAG, sent.to_be_bytes().to_vec()),
+        Packet::Pong(sent) => (PONG_TAG, sent.to_be_bytes().to_vec()),
+        Packet::Rejection(reason) => (REJECTION_TAG, reason.into_bytes()),
+    };
+    let mut frame = Vec::with_capacity(HEADER_LEN + 1 + payload.len());
+    frame.extend_from_slice(&((payload.len() + 1) as u32).to_be_bytes());
+    frame.push(tag);
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+fn decode_packet(tag: u8, payload: &[u8]) -> Result<Packet, String> {
+    match tag {
+        MOVE_TAG => NetworkMove::try_from(payload)
+            .map(Packet::Move)
+            .map_err(|e| format!("Error parsing move: {}", e)),
+        ACK_TAG => Ack::try_from(payload)
+            .map(Packet::Ack)
+            .map_err(|e| format!("Error parsing ack: {}", e)),
+        START_TAG => Start::try_from(payload)
+            .map(Packet::Start)
+            .map_err(|e| format!("Error parsing start: {}", e)),
+        HISTORY_TAG => std::str::from_utf8(payload)
+            .map(|moves| Packet::History(moves.split_whitespace().map(String::from).collect()))
+            .map_err(|e| format!("Error parsing history: {}", e)),
+        PING_TAG => decode_timestamp(payload).map(Packet::Ping),
+        PONG_TAG => decode_timestamp(payload).map(Packet::Pong),
+        REJECTION_TAG => String::from_utf8(payload.to_vec())
+            .map(Packet::Rejection)
+            .map_err(|e| format!("Error parsing rejection: {}", e)),
+        _ => Err(format!("Unknown packet tag: {}", tag)),
+    }
+}
+
+fn decode_timestamp(payload: &[u8]) -> Result<u64, String> {
+    payload
+        .try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| format!("Invalid ping length: {}", payload.len()))
+}
+
+// takes every complete frame out of the buffer, leaving a trailing partial frame in place
+fn split_frames(buffer: &mut Vec<u8>) -> io::Result<Vec<Packet>> {
+    let mut packets = vec![];
+    let mut consumed = 0;
+    while buffer.len() - consumed >= HEADER_LEN {
+        let header: [u8; HEADER_LEN] = buffer[consumed..consumed + HEADER_LEN].try_into().unwrap();
+        let frame_len = u32::from_be_bytes(header) as usize;
+        if frame_len == 0 || frame_len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid frame length: {}", frame_len),
+            ));
+        }
+        if buffer.len() - consumed - HEADER_LEN < frame_len {
+            break;
+        }
+        let frame_start = consumed + HEADER_LEN;
+        let frame = &buffer[frame_start..frame_start + frame_len];
+        match decode_packet(frame[0], &frame[1..]) {
+            Ok(packet) => packets.push(packet),
+            // a bad frame is dropped on its own, the next one still starts at the right place
+            Err(e) => println!("{}", e),
+        }
+        consumed = frame_start + frame_len;
+    }
+    buffer.drain(..consumed);
+    Ok(packets)
+}
+
+pub struct FramedStream {
+    stream: TcpStream,
+    read_buffer: Vec<u8>,
+    // bytes that the socket wasn't ready to take yet
+    write_buffer: Vec<u8>,
+    // set once the peer has closed its end of the connection
+    closed: bool,
+}
+
+impl FramedStream {
+    pub fn new(stream: TcpStream) -> FramedStream {
+        FramedStream {
+            stream,
+            read_buffer: vec![],
+            write_buffer: vec![],
+            closed: false,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn send(&mut self, packet: Packet) -> io::Result<()> {
+        let frame = encode_packet(packet).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        self.write_buffer.extend_from_slice(&frame);
+        self.flush_pending()
+    }
+
+    // writes as much of the queued data as the socket accepts without blocking
+    pub fn flush_pending(&mut self) -> io::Result<()> {
+        while !self.write_buffer.is_empty() {
+            match self.stream.write(&self.write_buffer) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+                Ok(written) => {
+                    self.write_buffer.drain(..written);
+                }
+                Err(ref e) if is_would_block(e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        self.stream.flush()
+    }
+
+    // reads everything available right now and returns the complete packets in order
+    pub fn receive(&mut self) -> io::Result<Vec<Packet>> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(size) => self.read_buffer.extend_from_slice(&buf[..size]),
+                Err(ref e) if is_would_block(e) => break,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        split_frames(&mut self.read_buffer)
+    }
+}
+
+fn is_would_block(e: &io::Error) -> bool {
+    e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(packet: Packet) -> Vec<u8> {
+        encode_packet(packet).unwrap()
+    }
+
+    #[test]
+    fn splits_frames_that_arrive_together() {
+        let mut buffer = frame(Packet::Ping(7));
+        buffer.extend(frame(Packet::History(vec![
+            String::from("e2e4"),
+            String::from("e7e5"),
+        ])));
+        let packets = split_frames(&mut buffer).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert!(matches!(packets[0], Packet::Ping(7)));
+        assert!(matches!(&packets[1], Packet::History(moves) if moves == &["e2e4", "e7e5"]));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn keeps_a_partial_frame_until_it_is_complete() {
+        let bytes = frame(Packet::Rejection(String::from("Illegal move")));
+        let mut buffer = vec![];
+        for (index, byte) in bytes.iter().enumerate() {
+            buffer.push(*byte);
+            let packets = split_frames(&mut buffer).unwrap();
+            if index + 1 < bytes.len() {
+                assert!(packets.is_empty());
+                assert_eq!(buffer.len(), index + 1);
+            } else {
+                assert_eq!(packets.len(), 1);
+                assert!(
+                    matches!(&packets[0], Packet::Rejection(reason) if reason == "Illegal move")
+                );
+                assert!(buffer.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn drops_an_unknown_tag_and_reads_on() {
+        let mut buffer = vec![0, 0, 0, 3, 42, 1, 2];
+        buffer.extend(frame(Packet::Pong(9)));
+        let packets = split_frames(&mut buffer).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(matches!(packets[0], Packet::Pong(9)));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn rejects_an_impossible_frame_length() {
+        let mut empty = vec![0, 0, 0, 0, PING_TAG];
+        let error = split_frames(&mut empty).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let mut oversized = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes().to_vec();
+        oversized.push(HISTORY_TAG);
+        let error = split_frames(&mut oversized).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
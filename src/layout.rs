@@ -0,0 +1,48 @@
+use ggez::glam::Vec2;
+use ggez::graphics::Rect;
+
+// the ui is laid out on a fixed design surface that is scaled to fit the window
+pub const DESIGN_WIDTH: f32 = 1200.0;
+pub const DESIGN_HEIGHT: f32 = 1200.0;
+
+// the board's top-left corner and square size on the design surface
+pub const BOARD_X: f32 = 100.0;
+pub const BOARD_Y: f32 = 100.0;
+pub const SQUARE_SIZE: f32 = 80.0;
+pub const BOARD_SIZE: f32 = SQUARE_SIZE * 8.0;
+
+// how the design surface maps onto the window, recomputed whenever the window is resized
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Layout {
+    // window pixels per design unit, the same in both directions so nothing is stretched
+    pub scale: f32,
+    // the part of the design surface's coordinate space that covers the whole window, wider or
+    // taller than the design surface when the window's shape differs from it
+    pub visible: Rect,
+}
+
+impl Layout {
+    pub fn new(window_width: f32, window_height: f32) -> Layout {
+        let scale = (window_width / DESIGN_WIDTH)
+            .min(window_height / DESIGN_HEIGHT)
+            .max(f32::EPSILON);
+        let width = window_width / scale;
+        let height = window_height / scale;
+        // the spare room is split evenly so the design surface sits in the middle
+        let visible = Rect::new(
+            -(width - DESIGN_WIDTH) / 2.0,
+            -(height - DESIGN_HEIGHT) / 2.0,
+            width,
+            height,
+        );
+        Layout { scale, visible }
+    }
+
+    // a point in window pixels, e.g. the mouse position, on the design surface
+    pub fn to_design(&self, x: f32, y: f32) -> Vec2 {
+        Vec2::new(
+            self.visible.x + (x / self.scale),
+            self.visible.y + (y / self.scale),
+        )
+    }
+}
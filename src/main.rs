@@ -1,12 +1,24 @@
-use network_helpers::{connect_to_host, handle_incoming_packages, listen_for_connections};
-use std::io::{ErrorKind, Write};
+use board_helpers::{is_in_check, king_square, opposite_color, square_to_index};
+use clock::{format_clock, ChessClock, TIME_CONTROLS};
+use framing::Packet;
+use layout::{Layout, BOARD_SIZE, BOARD_X, BOARD_Y, SQUARE_SIZE};
+use network_helpers::{
+    connect_to_host, handle_incoming_packages, listen_for_connections, poll_for_connection,
+    send_forfeit, send_package,
+};
+use network_worker::{NetworkWorker, DEFAULT_SILENCE_TIMEOUT, KEEPALIVE_INTERVAL};
+use pgn::{export_pgn, load_pgn, nag_to_string, pgn_date_today, save_pgn, PgnGame, PgnHeaders};
+use san::{first_move_number, move_rows, move_to_san};
 use std::{
     collections::HashMap,
     env,
-    io::Read,
-    net::{SocketAddr, TcpListener, TcpStream},
+    net::TcpListener,
     path,
+    sync::mpsc::{Receiver, TryRecvError},
+    time::Duration,
 };
+use uci::{uci_to_move, UciEngine, UciEvent, UciScore};
+use widgets::{Button, Label, Panel, TextInput};
 
 use chess_networking::{Ack, GameState, Move as NetworkMove, PromotionPiece, Start};
 use conf::WindowMode;
@@ -14,10 +26,21 @@ use dexterws_chess::game::{
     Board, Color as PieceColor, File, GameResult as ChessResult, Move, Piece, Rank, Square,
 };
 use event::MouseButton;
+use ggez::input::keyboard::{KeyCode, KeyInput};
 use ggez::*;
 use glam::Vec2;
 use graphics::{Color, Drawable, FillOptions, MeshBuilder, Text};
+pub mod ai;
+pub mod board_helpers;
+pub mod clock;
+pub mod framing;
+pub mod layout;
 pub mod network_helpers;
+pub mod network_worker;
+pub mod pgn;
+pub mod san;
+pub mod uci;
+pub mod widgets;
 fn piece_to_promotion_piece(piece: Option<Piece>) -> Option<PromotionPiece> {
     match piece {
         Some(Piece::Queen) => Some(PromotionPiece::Queen),
@@ -38,6 +61,92 @@ fn promotion_piece_to_piece(piece: Option<PromotionPiece>) -> Option<Piece> {
     }
 }
 
+// text fields in the pre-game ui that can receive keyboard input
+#[derive(Clone, Copy, PartialEq)]
+enum TextInputField {
+    Fen,
+    Address,
+    EnginePath,
+    PgnPath,
+    Timeout,
+}
+
+// what a button does when it is clicked
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum UiAction {
+    Host,
+    Join,
+    InitGame,
+    LeaveLobby,
+    BackToMenu,
+    Reconnect,
+    CancelReconnect,
+    ChooseColor(PieceColor),
+    TimeControl(usize),
+    LocalGame,
+    ComputerGame,
+    EngineGame,
+    DepthDown,
+    DepthUp,
+    ToggleAnalysis,
+    Forfeit,
+    OfferDraw,
+    AcceptDraw,
+    RejectDraw,
+    ExportPgn,
+    LoadPgn,
+    ReplayFirst,
+    ReplayBack,
+    ReplayForward,
+    ReplayLast,
+    ReplayClose,
+    BackToLive,
+}
+
+// who is playing the other side of the board
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameMode {
+    // against a peer over tcp, moves are played once acknowledged
+    Network,
+    // both colors on this machine, moves go straight to the board
+    Local,
+    // against the built-in engine, which searches on its own thread
+    Computer { computer_color: PieceColor },
+    // against an external uci engine process
+    Engine { engine_color: PieceColor },
+}
+
+// a move that has been played, with its notation computed from the position before it
+#[derive(Clone, PartialEq, Debug)]
+pub struct PastMove {
+    pub chess_move: Move,
+    pub san: String,
+}
+
+// why a game ended when it wasn't decided on the board
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EndReason {
+    // the color whose clock ran out
+    Timeout(PieceColor),
+    // the color that forfeited
+    Forfeit(PieceColor),
+    DrawAgreed,
+}
+
+// the screen the app is on, it decides what is drawn and which actions can be taken
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Phase {
+    // picking an opponent, color, time control and start position, or replaying a pgn file
+    Menu,
+    // hosting or joined, until the start package has been exchanged
+    Lobby,
+    InGame,
+    // the opponent dropped out of a game in progress, it is paused until they are back
+    Disconnected,
+    // the game has been decided, its final position stays up until going back to the menu
+    GameOver,
+}
+
 struct State {
     //    image: graphics::Image,
     rect: graphics::Mesh,
@@ -46,18 +155,99 @@ struct State {
     mouse_down: bool,
     current_legal_moves: Option<Vec<Move>>,
     selected_square: Option<Square>,
-    past_moves: Vec<(PieceColor, Move)>,
+    // oldest first
+    past_moves: Vec<PastMove>,
     // None = not connected, true = host, false = join
     is_host: Option<bool>,
     selected_color: Option<PieceColor>,
-    client_stream: Option<TcpStream>,
+    connection: Option<NetworkWorker>,
+    // set while hosting and waiting for an opponent to connect
+    listener: Option<TcpListener>,
     start: Option<Start>,
+    // moves the host sent ahead of the start package of a resumed game
+    resume_history: Option<Vec<String>>,
     pending_chess_move: Option<Move>,
-    game_has_ended: bool,
+    // why the host refused our last move, it arrives just before the ack that refuses it
+    rejection_reason: Option<String>,
+    // set when the opponent's idea of how the game ended differs from our own board's
+    protocol_dispute: Option<String>,
+    phase: Phase,
     offer_draw_received: bool,
     offer_draw_sent: bool,
+    // FEN typed by the client before sending the start package, empty = standard position
+    fen_input: String,
+    // FEN the current game was started from, None = standard position
+    start_fen: Option<String>,
+    // address the host binds to and the client connects to
+    address_input: String,
+    focused_input: Option<TextInputField>,
+    error_message: Option<String>,
+    // index into TIME_CONTROLS picked by the client before sending the start package
+    selected_time_control: usize,
+    clock: Option<ChessClock>,
+    end_reason: Option<EndReason>,
+    // promotion moves to pick from while the promotion dialog is open
+    promotion_choices: Option<Vec<(Move, graphics::Image)>>,
+    game_mode: GameMode,
+    // search depth used by the computer opponent
+    ai_depth: u32,
+    // the computer's move arrives here once it has finished searching
+    ai_search: Option<Receiver<Option<Move>>>,
+    // command used to start the uci engine
+    engine_path_input: String,
+    engine: Option<UciEngine>,
+    // number of moves played and side to move in the position the engine is searching
+    engine_searched: Option<(usize, PieceColor)>,
+    // the engine's latest score and depth, for the side to move in the position it searched, shown
+    // while analysis is on or when playing the engine
+    engine_evaluation: Option<(UciScore, PieceColor, Option<u32>)>,
+    analysis_enabled: bool,
+    // name the client sent in its start package, only known on the host
+    opponent_name: Option<String>,
+    // set once the finished game has been written to a pgn file
+    pgn_saved: bool,
+    info_message: Option<String>,
+    // path of the pgn file to open in the replay viewer
+    pgn_path_input: String,
+    // seconds the opponent may stay silent before the connection is given up
+    timeout_input: String,
+    // game loaded from a pgn file, the board shows it after replay_ply moves
+    replay: Option<PgnGame>,
+    replay_ply: usize,
+    // first row shown in the move list
+    move_list_scroll: usize,
+    // set while looking at an earlier position, the board can't be played on until back to live
+    viewing_ply: Option<usize>,
+    viewed_board: Option<Board>,
+    viewed_images: Option<[Option<graphics::Image>; 64]>,
+    // true when black is shown at the bottom of the board
+    flipped: bool,
+    // the square of the piece being dragged and where the cursor has taken it, the drag only
+    // counts once the cursor has left the piece's square
+    drag_square: Option<Square>,
+    drag_position: Option<Vec2>,
+    // where the design surface sits in the window, all drawing and hit-testing goes through it
+    layout: Layout,
 }
 
+// the move list panel to the right of the board
+const MOVE_LIST_X: f32 = 950.0;
+const MOVE_LIST_Y: f32 = 100.0;
+const MOVE_LIST_WIDTH: f32 = 240.0;
+const MOVE_LIST_ROW_HEIGHT: f32 = 25.0;
+// rows visible at once, the rest is reached by scrolling
+const MOVE_LIST_ROWS: usize = 24;
+// the columns holding white's and black's moves, the move number sits left of them
+const MOVE_LIST_WHITE_X: f32 = MOVE_LIST_X + 55.0;
+const MOVE_LIST_BLACK_X: f32 = MOVE_LIST_WHITE_X + MOVE_LIST_COLUMN_WIDTH;
+const MOVE_LIST_COLUMN_WIDTH: f32 = 90.0;
+
+// how long the engine thinks about each of its moves, in milliseconds
+const ENGINE_MOVE_TIME: u64 = 1000;
+
+// order of the choices in the promotion dialog
+const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
 fn piece_to_image(piece: (Piece, PieceColor)) -> String {
     let mut piece_image_map: HashMap<(Piece, PieceColor), String> = HashMap::new();
     piece_image_map.insert(
@@ -112,6 +302,33 @@ fn piece_to_image(piece: (Piece, PieceColor)) -> String {
     return piece_image_map.get(&piece).unwrap().clone();
 }
 
+fn load_piece_images(ctx: &mut Context, board: &Board) -> [Option<graphics::Image>; 64] {
+    board
+        .get_all_pieces()
+        .map(|piece| piece.map(|p| graphics::Image::from_path(ctx, piece_to_image(p)).unwrap()))
+}
+
+// empty or missing FEN gives the standard starting position
+pub fn board_from_fen(fen: Option<&str>) -> Result<Board, String> {
+    match fen.map(|f| f.trim()).filter(|f| !f.is_empty()) {
+        Some(f) => Board::from_fen(f).map_err(|e| format!("Invalid FEN: {}", e)),
+        None => Ok(Board::new()),
+    }
+}
+
+// score from the side to move's point of view, turned into white's point of view
+fn format_evaluation(score: UciScore, side: PieceColor, depth: Option<u32>) -> String {
+    let sign = if side == PieceColor::White { 1 } else { -1 };
+    let score = match score {
+        UciScore::Centipawns(cp) => format!("{:+.2}", (sign * cp) as f32 / 100.0),
+        UciScore::Mate(moves) => format!("#{}", sign * moves),
+    };
+    match depth {
+        Some(depth) => format!("{} (depth {})", score, depth),
+        None => score,
+    }
+}
+
 fn draw_board(mb: &mut MeshBuilder) {
     let white_square_color = graphics::Color::new(0.94, 0.85, 0.71, 1.0);
     let black_square_color = graphics::Color::new(0.71, 0.53, 0.39, 1.0);
@@ -120,10 +337,10 @@ fn draw_board(mb: &mut MeshBuilder) {
             mb.rectangle(
                 graphics::DrawMode::fill(),
                 graphics::Rect::new(
-                    100.0 + (j as f32 * 80.0),
-                    100.0 + (i as f32 * 80.0),
-                    80.0,
-                    80.0,
+                    BOARD_X + (j as f32 * SQUARE_SIZE),
+                    BOARD_Y + (i as f32 * SQUARE_SIZE),
+                    SQUARE_SIZE,
+                    SQUARE_SIZE,
                 ),
                 if (i + j) % 2 == 1 {
                     black_square_color
@@ -136,89 +353,288 @@ fn draw_board(mb: &mut MeshBuilder) {
     }
 }
 
-fn draw_restart_button(canvas: &mut graphics::Canvas, ctx: &mut Context, is_host: Option<bool>) {
-    let button_host = graphics::Mesh::new_rounded_rectangle(
-        ctx,
-        graphics::DrawMode::fill(),
-        graphics::Rect::new(640.0, 800.0, 150.0, 40.0),
-        5.0,
-        graphics::Color::new(1.0, 0.0, 0.0, 1.0),
-    )
-    .unwrap();
-    let button_join = graphics::Mesh::new_rounded_rectangle(
-        ctx,
-        graphics::DrawMode::fill(),
-        graphics::Rect::new(640.0, 850.0, 150.0, 40.0),
-        5.0,
-        graphics::Color::new(1.0, 0.0, 0.0, 1.0),
+// the top-left corner of a square on screen, white's view has rank 8 at the top and black's
+// view turns the board around
+fn square_position(square: Square, flipped: bool) -> Vec2 {
+    let (file, rank) = (square.file.to_idx() as f32, square.rank.to_idx() as f32);
+    let (column, row) = if flipped {
+        (7.0 - file, rank)
+    } else {
+        (file, 7.0 - rank)
+    };
+    Vec2::new(
+        BOARD_X + (column * SQUARE_SIZE),
+        BOARD_Y + (row * SQUARE_SIZE),
     )
-    .unwrap();
+}
+
+// the square under a point on screen, the inverse of square_position
+fn square_at(x: f32, y: f32, flipped: bool) -> Option<Square> {
+    if !(BOARD_X..BOARD_X + BOARD_SIZE).contains(&x)
+        || !(BOARD_Y..BOARD_Y + BOARD_SIZE).contains(&y)
+    {
+        return None;
+    }
+    let column = ((x - BOARD_X) / SQUARE_SIZE) as u8;
+    let row = ((y - BOARD_Y) / SQUARE_SIZE) as u8;
+    let (file, rank) = if flipped {
+        (7 - column, row)
+    } else {
+        (column, 7 - row)
+    };
+    Some(Square::from_idx(rank * 8 + file))
+}
 
-    let button_init_game = graphics::Mesh::new_rounded_rectangle(
+fn draw_square_highlight(
+    canvas: &mut graphics::Canvas,
+    ctx: &mut Context,
+    square: Square,
+    flipped: bool,
+    color: Color,
+) -> GameResult {
+    let position = square_position(square, flipped);
+    let highlight = graphics::Mesh::new_rectangle(
         ctx,
         graphics::DrawMode::fill(),
-        graphics::Rect::new(640.0, 900.0, 150.0, 40.0),
-        5.0,
-        graphics::Color::new(1.0, 0.0, 0.0, 1.0),
-    )
-    .unwrap();
-
-    let button_text_host = Text::new("New game + (host)");
-    let button_text_join = Text::new("New game + (join)");
-    let button_text_init_game = Text::new("Init game");
-    let text_position_host = glam::Vec2::new(650.0, 810.0);
-    let text_position_join = glam::Vec2::new(650.0, 860.0);
-    let text_position_init_game = glam::Vec2::new(650.0, 910.0);
-    button_host.draw(canvas, graphics::DrawParam::default());
-    button_join.draw(canvas, graphics::DrawParam::default());
-    button_text_host.draw(
+        graphics::Rect::new(position.x, position.y, SQUARE_SIZE, SQUARE_SIZE),
+        color,
+    )?;
+    canvas.draw(&highlight, graphics::DrawParam::default().z(50));
+    Ok(())
+}
+
+// players, result and the move last played with its annotations, above the board
+fn draw_replay_info(canvas: &mut graphics::Canvas, replay: &PgnGame, ply: usize) {
+    Text::new(format!(
+        "{} vs {} ({})",
+        replay.header("White").unwrap_or("?"),
+        replay.header("Black").unwrap_or("?"),
+        replay.header("Result").unwrap_or("*"),
+    ))
+    .draw(
         canvas,
-        graphics::DrawParam::default()
-            .z(100)
-            .dest(text_position_host),
+        graphics::DrawParam::new().dest(glam::Vec2::new(350.0, 20.0)),
     );
-    button_text_join.draw(
+    let last_move = ply.checked_sub(1).map(|index| &replay.moves[index]);
+    let mut move_text = format!("Move {} of {}", ply, replay.moves.len());
+    if let Some(pgn_move) = last_move {
+        move_text.push_str(": ");
+        move_text.push_str(&pgn_move.san);
+        for nag in &pgn_move.nags {
+            move_text.push_str(&nag_to_string(*nag));
+        }
+    }
+    Text::new(move_text).draw(
         canvas,
-        graphics::DrawParam::default()
-            .z(100)
-            .dest(text_position_join),
+        graphics::DrawParam::new().dest(glam::Vec2::new(350.0, 45.0)),
     );
-    if is_host.is_some() && is_host.unwrap() == false {
-        button_init_game.draw(canvas, graphics::DrawParam::default());
-        button_text_init_game.draw(
+    if let Some(comment) = last_move.and_then(|pgn_move| pgn_move.comment.as_ref()) {
+        let mut comment_text = Text::new(comment.as_str());
+        comment_text.set_bounds(glam::Vec2::new(440.0, 25.0));
+        comment_text.draw(
             canvas,
-            graphics::DrawParam::default()
-                .z(100)
-                .dest(text_position_init_game),
+            graphics::DrawParam::new().dest(glam::Vec2::new(350.0, 70.0)),
         );
     }
 }
 
-fn draw_color_picker(canvas: &mut graphics::Canvas, ctx: &mut Context) {
-    let label_text = Text::new("Choose color");
-    let label_position = glam::Vec2::new(500.0, 780.0);
-    let white_rect = graphics::Mesh::new_rounded_rectangle(
+// whether a point is over the move list panel, for clicking and scrolling it
+fn move_list_contains(x: f32, y: f32) -> bool {
+    let list_bottom = MOVE_LIST_Y + (MOVE_LIST_ROWS as f32 * MOVE_LIST_ROW_HEIGHT);
+    (MOVE_LIST_X..=MOVE_LIST_X + MOVE_LIST_WIDTH).contains(&x)
+        && (MOVE_LIST_Y..list_bottom).contains(&y)
+}
+
+// two columns of numbered moves in san, e.g. "12. Nxe5+ Qxe5", starting at row `scroll`
+fn draw_move_list(
+    canvas: &mut graphics::Canvas,
+    ctx: &mut Context,
+    start_fen: Option<&str>,
+    past_moves: &[PastMove],
+    scroll: usize,
+    shown_ply: Option<usize>,
+) -> GameResult {
+    let panel = graphics::Mesh::new_rectangle(
         ctx,
         graphics::DrawMode::fill(),
-        graphics::Rect::new(500.0, 800.0, 60.0, 40.0),
-        5.0,
-        graphics::Color::new(1.0, 1.0, 1.0, 1.0),
-    )
-    .unwrap();
+        graphics::Rect::new(
+            MOVE_LIST_X,
+            MOVE_LIST_Y,
+            MOVE_LIST_WIDTH,
+            MOVE_LIST_ROWS as f32 * MOVE_LIST_ROW_HEIGHT,
+        ),
+        graphics::Color::new(0.05, 0.1, 0.15, 1.0),
+    )?;
+    panel.draw(canvas, graphics::DrawParam::default());
+
+    let (first_number, white_first) = first_move_number(start_fen);
+    let offset = if white_first { 0 } else { 1 };
+    let sans: Vec<String> = past_moves.iter().map(|m| m.san.clone()).collect();
+    let rows = move_rows(first_number, white_first, &sans);
+    for (row, (number, white, black)) in rows
+        .into_iter()
+        .enumerate()
+        .skip(scroll)
+        .take(MOVE_LIST_ROWS)
+    {
+        let y = MOVE_LIST_Y + ((row - scroll) as f32 * MOVE_LIST_ROW_HEIGHT);
+        if let Some(ply) = shown_ply.filter(|ply| *ply >= 1) {
+            if (ply + offset - 1) / 2 == row {
+                let x = if (ply + offset) % 2 == 1 {
+                    MOVE_LIST_WHITE_X
+                } else {
+                    MOVE_LIST_BLACK_X
+                };
+                let highlight = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    graphics::Rect::new(
+                        x,
+                        y + 2.0,
+                        MOVE_LIST_COLUMN_WIDTH,
+                        MOVE_LIST_ROW_HEIGHT - 4.0,
+                    ),
+                    graphics::Color::new(0.3, 0.4, 0.6, 1.0),
+                )?;
+                highlight.draw(canvas, graphics::DrawParam::default());
+            }
+        }
+        Text::new(format!("{}.", number)).draw(
+            canvas,
+            graphics::DrawParam::default().dest(glam::Vec2::new(MOVE_LIST_X + 10.0, y + 5.0)),
+        );
+        Text::new(white.unwrap_or("...")).draw(
+            canvas,
+            graphics::DrawParam::default().dest(glam::Vec2::new(MOVE_LIST_WHITE_X + 5.0, y + 5.0)),
+        );
+        if let Some(black) = black {
+            Text::new(black).draw(
+                canvas,
+                graphics::DrawParam::default()
+                    .dest(glam::Vec2::new(MOVE_LIST_BLACK_X + 5.0, y + 5.0)),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn draw_clocks(canvas: &mut graphics::Canvas, ctx: &mut Context, clock: &ChessClock) -> GameResult {
+    for (index, color) in [PieceColor::White, PieceColor::Black].iter().enumerate() {
+        let y = 160.0 + (index as f32 * 50.0);
+        let is_active = clock.is_running() && clock.active() == *color;
+        let background = graphics::Mesh::new_rounded_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(800.0, y, 150.0, 40.0),
+            5.0,
+            if is_active {
+                graphics::Color::new(0.9, 0.9, 0.9, 1.0)
+            } else {
+                graphics::Color::new(0.4, 0.4, 0.4, 1.0)
+            },
+        )?;
+        let name = if *color == PieceColor::White {
+            "White"
+        } else {
+            "Black"
+        };
+        let mut clock_text = Text::new(format!(
+            "{} {}",
+            name,
+            format_clock(clock.remaining(*color))
+        ));
+        clock_text.set_scale(24.0);
+
+        background.draw(canvas, graphics::DrawParam::default());
+        clock_text.draw(
+            canvas,
+            graphics::DrawParam::default()
+                .dest(glam::Vec2::new(810.0, y + 8.0))
+                .color(graphics::Color::BLACK)
+                .z(100),
+        );
+    }
+    Ok(())
+}
 
-    let black_rect = graphics::Mesh::new_rounded_rectangle(
+// a dot colored by how quickly the opponent answers, with the round trip time next to it, drawn
+// below the clocks and the evaluation
+fn draw_connection_quality(
+    canvas: &mut graphics::Canvas,
+    ctx: &mut Context,
+    connection: &NetworkWorker,
+) -> GameResult {
+    let silent_for = connection.silent_for();
+    let (color, text) = match connection.round_trip() {
+        // a couple of missed pings, the connection may be about to time out
+        _ if silent_for > KEEPALIVE_INTERVAL * 3 => (
+            Color::new(0.9, 0.1, 0.1, 1.0),
+            format!("No answer for {} s", silent_for.as_secs()),
+        ),
+        Some(round_trip) => {
+            let color = if round_trip < Duration::from_millis(100) {
+                Color::new(0.1, 0.8, 0.1, 1.0)
+            } else if round_trip < Duration::from_millis(300) {
+                Color::new(0.9, 0.8, 0.1, 1.0)
+            } else {
+                Color::new(0.9, 0.4, 0.1, 1.0)
+            };
+            (color, format!("Ping {} ms", round_trip.as_millis()))
+        }
+        None => (Color::new(0.5, 0.5, 0.5, 1.0), String::from("Ping …")),
+    };
+    let dot = graphics::Mesh::new_circle(
         ctx,
         graphics::DrawMode::fill(),
-        graphics::Rect::new(500.0, 850.0, 60.0, 40.0),
-        5.0,
-        graphics::Color::new(0.0, 0.0, 0.0, 1.0),
-    )
-    .unwrap();
+        Vec2::new(808.0, 338.0),
+        6.0,
+        0.5,
+        color,
+    )?;
+    canvas.draw(&dot, graphics::DrawParam::default());
+    Label::new(Vec2::new(820.0, 330.0), text).draw(canvas);
+    Ok(())
+}
 
-    label_text.draw(canvas, graphics::DrawParam::default().dest(label_position));
+// where the promotion choice at `index` sits on the board, used both to draw it and to click it
+fn promotion_choice_rect(index: usize) -> graphics::Rect {
+    graphics::Rect::new(260.0 + (index as f32 * 90.0), 380.0, 80.0, 80.0)
+}
 
-    white_rect.draw(canvas, graphics::DrawParam::default());
-    black_rect.draw(canvas, graphics::DrawParam::default());
+fn draw_promotion_dialog(
+    canvas: &mut graphics::Canvas,
+    ctx: &mut Context,
+    choices: &[(Move, graphics::Image)],
+) -> GameResult {
+    Panel::new(
+        graphics::Rect::new(BOARD_X, BOARD_Y, BOARD_SIZE, BOARD_SIZE),
+        graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+    )
+    .with_z(200)
+    .draw(canvas, ctx)?;
+    Label::new(glam::Vec2::new(260.0, 350.0), "Promote to")
+        .with_z(201)
+        .draw(canvas);
+    canvas.set_sampler(graphics::Sampler::nearest_clamp());
+    for (index, (_, image)) in choices.iter().enumerate() {
+        let rect = promotion_choice_rect(index);
+        let background = graphics::Mesh::new_rounded_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            rect,
+            5.0,
+            graphics::Color::new(0.94, 0.85, 0.71, 1.0),
+        )?;
+        background.draw(canvas, graphics::DrawParam::default().z(201));
+        canvas.draw(
+            image,
+            graphics::DrawParam::new()
+                .dest(glam::Vec2::new(rect.x + 10.0, rect.y + 10.0))
+                .z(202),
+        );
+    }
+    canvas.set_default_sampler();
+    Ok(())
 }
 
 impl State {
@@ -236,6 +652,7 @@ impl State {
         });
 
         let rect = graphics::Mesh::from_data(ctx, mb.build());
+        let (window_width, window_height) = ctx.gfx.drawable_size();
         let s = State {
             rect,
             board,
@@ -246,410 +663,1727 @@ impl State {
             past_moves: vec![],
             is_host: None,
             selected_color: None,
-            client_stream: None,
+            connection: None,
+            listener: None,
             start: None,
+            resume_history: None,
             pending_chess_move: None,
-            game_has_ended: false,
+            rejection_reason: None,
+            protocol_dispute: None,
+            phase: Phase::Menu,
             offer_draw_received: false,
             offer_draw_sent: false,
+            fen_input: String::new(),
+            start_fen: None,
+            address_input: String::from("127.0.0.1:8080"),
+            focused_input: None,
+            error_message: None,
+            selected_time_control: 0,
+            clock: None,
+            end_reason: None,
+            promotion_choices: None,
+            game_mode: GameMode::Network,
+            ai_depth: 3,
+            ai_search: None,
+            engine_path_input: String::from("stockfish"),
+            engine: None,
+            engine_searched: None,
+            engine_evaluation: None,
+            analysis_enabled: false,
+            opponent_name: None,
+            pgn_saved: false,
+            info_message: None,
+            pgn_path_input: String::from("game.pgn"),
+            timeout_input: DEFAULT_SILENCE_TIMEOUT.as_secs().to_string(),
+            replay: None,
+            replay_ply: 0,
+            move_list_scroll: 0,
+            viewing_ply: None,
+            viewed_board: None,
+            viewed_images: None,
+            flipped: false,
+            drag_square: None,
+            drag_position: None,
+            layout: Layout::new(window_width, window_height),
         };
 
         Ok(s)
     }
-}
-
-impl event::EventHandler<ggez::GameError> for State {
-    fn update(&mut self, ctx: &mut Context) -> GameResult {
-        const DESIRED_FPS: u32 = 60;
 
+    // sets up the board for a new game, either from the standard position or from a FEN
+    pub fn reset_board(&mut self, ctx: &mut Context, fen: Option<String>) -> Result<(), String> {
+        let board = board_from_fen(fen.as_deref())?;
+        self.piece_images = load_piece_images(ctx, &board);
+        self.board = board;
+        self.start_fen = fen.map(|f| f.trim().to_string()).filter(|f| !f.is_empty());
+        self.past_moves = vec![];
+        self.current_legal_moves = None;
+        self.selected_square = None;
+        self.pending_chess_move = None;
+        self.protocol_dispute = None;
+        self.clock = None;
+        self.end_reason = None;
+        self.promotion_choices = None;
+        self.ai_search = None;
+        if let Some(engine) = self.engine.as_mut() {
+            if let Err(e) = engine.stop() {
+                println!("Error stopping engine: {}", e);
+            }
+        }
+        self.engine_searched = None;
+        self.engine_evaluation = None;
+        self.pgn_saved = false;
+        self.info_message = None;
+        self.replay = None;
+        self.replay_ply = 0;
+        self.move_list_scroll = 0;
+        self.viewing_ply = None;
+        self.viewed_board = None;
+        self.viewed_images = None;
+        self.drag_square = None;
+        self.drag_position = None;
         Ok(())
     }
 
-    fn mouse_button_down_event(
-        &mut self,
-        ctx: &mut Context,
-        button: MouseButton,
-        x: f32,
-        y: f32,
-    ) -> GameResult {
-        self.mouse_down = true;
+    fn focused_input_text(&mut self) -> Option<&mut String> {
+        match self.focused_input {
+            Some(TextInputField::Fen) => Some(&mut self.fen_input),
+            Some(TextInputField::Address) => Some(&mut self.address_input),
+            Some(TextInputField::EnginePath) => Some(&mut self.engine_path_input),
+            Some(TextInputField::PgnPath) => Some(&mut self.pgn_path_input),
+            Some(TextInputField::Timeout) => Some(&mut self.timeout_input),
+            None => None,
+        }
+    }
 
-        if x < 740.0 && y < 740.0 && x > 100.0 && y > 100.0 {
-            let file: u8 = ((x - 110.0) / 80.0) as u8;
-            let rank: u8 = ((y - 110.0) / 80.0) as u8;
-            println!("{}, {}, x: {}, y: {}", file, rank, x, y);
-            let index = (rank * 8) + file;
+    pub fn silence_timeout(&self) -> Result<Duration, String> {
+        match self.timeout_input.trim().parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+            _ => Err(format!(
+                "Invalid timeout: {}, expected a number of seconds",
+                self.timeout_input.trim()
+            )),
+        }
+    }
 
-            let square = Square::from_idx(index);
-            let piece = self.board.get_piece(square);
+    // time and increment in seconds, as sent in the start package
+    pub fn start_clock(&mut self, time: Option<u64>, inc: Option<u64>) {
+        self.clock = time.map(|time| {
+            let mut clock = ChessClock::new(time, inc.unwrap_or(0), self.board.side());
+            clock.start();
+            clock
+        });
+    }
 
-            if self.current_legal_moves.is_some()
-                && self
-                    .current_legal_moves
-                    .as_ref()
-                    .unwrap()
-                    .iter()
-                    .find(|m| m.to().file.to_idx() == file && m.to().rank.to_idx() == rank)
-                    .is_some()
-            {
-                let selected_move = self
-                    .current_legal_moves
-                    .as_ref()
-                    .unwrap()
-                    .iter()
-                    .find(|m| m.to().file.to_idx() == file && m.to().rank.to_idx() == rank)
-                    .unwrap();
+    pub fn is_in_game(&self) -> bool {
+        matches!(
+            self.phase,
+            Phase::InGame | Phase::Disconnected | Phase::GameOver
+        )
+    }
 
-                self.pending_chess_move = Some(*selected_move);
-                let promotion_piece = match selected_move.promotion() {
-                    Some(p) => Some(p),
-                    None => None,
-                };
-                println!("Selected move: {:?}", selected_move);
-                let network_move = NetworkMove {
-                    from: (
-                        selected_move.from().file.to_idx(),
-                        selected_move.from().rank.to_idx(),
-                    ),
-                    to: (
-                        selected_move.to().file.to_idx(),
-                        selected_move.to().rank.to_idx(),
-                    ),
-                    forfeit: false,
-                    offer_draw: false,
-                    promotion: piece_to_promotion_piece(promotion_piece),
-                };
-                let network_move_bytes: Vec<u8> = network_move.try_into().unwrap();
-                match self.client_stream.as_mut() {
-                    Some(stream) => match stream.write_all(&network_move_bytes) {
-                        Ok(_) => println!("Move sent successfully"),
-                        Err(e) => println!("Error sending move: {}", e),
-                    },
-                    None => println!("No client stream found"),
+    // the color controlled from this window, in a local game that is whoever's turn it is
+    pub fn player_color(&self) -> Option<PieceColor> {
+        match self.game_mode {
+            GameMode::Network => self.start.as_ref().map(|start| {
+                if start.is_white {
+                    PieceColor::White
+                } else {
+                    PieceColor::Black
                 }
-
-                self.current_legal_moves = None;
-            } else if piece.is_some_and(|p| p.0 == PieceColor::White)
-                && self.start.as_ref().unwrap().is_white == true
-                || piece.is_some_and(|p| p.0 == PieceColor::Black)
-                    && self.start.as_ref().unwrap().is_white == false
-            {
-                // We are inside the board
-                println!("Selected square: {:?}", square);
-                let legal_moves = self.board.get_moves(square);
-                println!("Legal moves: {:?}", legal_moves);
-                self.current_legal_moves = legal_moves;
-                self.selected_square = Some(square);
-            }
+            }),
+            _ if !self.is_in_game() => None,
+            GameMode::Local => Some(self.board.side()),
+            GameMode::Computer { computer_color } => Some(opposite_color(computer_color)),
+            GameMode::Engine { engine_color } => Some(opposite_color(engine_color)),
         }
+    }
 
-        // restart button (host) has been pressed
-        if x >= 640.0 && x <= 750.0 && y >= 800.0 && y <= 840.0 {
-            self.current_legal_moves = Some(vec![]);
-            self.past_moves = vec![];
-            self.selected_square = None;
-            self.board = Board::new();
-            let piece_images: [Option<graphics::Image>; 64] =
-                self.board.get_all_pieces().map(|piece| match piece {
-                    Some(p) => Some(graphics::Image::from_path(ctx, piece_to_image(p)).unwrap()),
-                    None => None,
-                });
-            self.piece_images = piece_images;
-            listen_for_connections(self);
-            self.is_host = Some(true);
-        }
-
-        // restart button (join) has been pressed
-        if x >= 640.0 && x <= 750.0 && y >= 850.0 && y <= 890.0 {
-            connect_to_host("127.0.0.1:8080".to_string(), self);
-        }
-        if x >= 500.0 && x <= 560.0 && y >= 800.0 && y <= 840.0 {
-            self.selected_color = Some(PieceColor::White);
-        }
-        if x >= 500.0 && x <= 560.0 && y >= 850.0 && y <= 890.0 {
-            self.selected_color = Some(PieceColor::Black);
-        }
-        if x >= 800.0 && x <= 860.0 && y >= 60.0 && y <= 100.0 {
-            let forfeit_package = NetworkMove {
-                from: (0, 0),
-                to: (0, 0),
-                promotion: None,
-                forfeit: true,
-                offer_draw: false,
-            };
-            let forfeit_package_bytes: Vec<u8> = forfeit_package.try_into().unwrap();
-            self.game_has_ended = true;
-            if let Some(stream) = self.client_stream.as_mut() {
-                match stream.write_all(&forfeit_package_bytes) {
-                    Ok(_) => match stream.flush() {
-                        Ok(_) => println!("Stream flushed successfully"),
-                        Err(e) => println!("Error flushing stream: {}", e),
-                    },
-                    Err(e) => println!("Error sending forfeit package: {}", e),
-                }
-            }
-        }
-        if x >= 800.0 && x <= 860.0 && y >= 110.0 && y <= 150.0 {
-            let offer_draw_package = NetworkMove {
-                from: (0, 0),
-                to: (0, 0),
-                promotion: None,
-                forfeit: false,
-                offer_draw: true,
-            };
-            let offer_draw_package_bytes: Vec<u8> = offer_draw_package.try_into().unwrap();
-            self.offer_draw_sent = true;
-            if let Some(stream) = self.client_stream.as_mut() {
-                match stream.write_all(&offer_draw_package_bytes) {
-                    Ok(_) => match stream.flush() {
-                        Ok(_) => println!("Stream flushed successfully"),
-                        Err(e) => println!("Error flushing stream: {}", e),
-                    },
-                    Err(e) => println!("Error sending offer draw package: {}", e),
-                }
-            }
-        }
+    // the moves of the current game, oldest first
+    pub fn move_history(&self) -> Vec<Move> {
+        self.past_moves.iter().map(|m| m.chess_move).collect()
+    }
 
-        if x >= 100.0 && x <= 210.0 && y >= 60.0 && y <= 100.0 {
-            self.offer_draw_received = false;
-            let ack_package = Ack {
-                ok: true,
-                end_state: Some(GameState::Draw),
-            };
-            self.game_has_ended = true;
-            let ack_package_bytes: Vec<u8> = ack_package.try_into().unwrap();
-            if let Some(stream) = self.client_stream.as_mut() {
-                match stream.write_all(&ack_package_bytes) {
-                    Ok(_) => match stream.flush() {
-                        Ok(_) => println!("Stream flushed successfully"),
-                        Err(e) => println!("Error flushing stream: {}", e),
-                    },
-                    Err(e) => println!("Error sending ack package: {}", e),
-                }
-            }
+    // plays a move that both sides have agreed on and hands the clock over to the other side
+    pub fn apply_move(&mut self, ctx: &mut Context, chess_move: Move) -> Result<(), String> {
+        if self.phase != Phase::InGame {
+            return Err(String::from("No game is being played"));
         }
-        if x >= 220.0 && x <= 330.0 && y >= 60.0 && y <= 100.0 {
-            self.offer_draw_received = false;
-            let ack_package = Ack {
-                ok: false,
-                end_state: None,
-            };
-            let ack_package_bytes: Vec<u8> = ack_package.try_into().unwrap();
-            if let Some(stream) = self.client_stream.as_mut() {
-                match stream.write_all(&ack_package_bytes) {
-                    Ok(_) => match stream.flush() {
-                        Ok(_) => println!("Stream flushed successfully"),
-                        Err(e) => println!("Error flushing stream: {}", e),
-                    },
-                    Err(e) => println!("Error sending ack package: {}", e),
-                }
-            }
+        let mover = self.board.side();
+        let san = move_to_san(&self.board, chess_move);
+        self.board
+            .make_move(chess_move)
+            .map_err(|e| e.to_string())?;
+        self.past_moves.push(PastMove { chess_move, san });
+        // follow the game unless an earlier position is being looked at
+        if self.viewing_ply.is_none() {
+            self.scroll_to_ply(self.past_moves.len());
         }
-        if x >= 640.0
-            && x <= 750.0
-            && y >= 900.0
-            && y <= 940.0
-            && self.is_host.is_some()
-            && self.is_host.unwrap() == false
-        {
-            let start_package = Start {
-                is_white: self.selected_color.unwrap() == PieceColor::White,
-                name: Some("Alexander".to_string()),
-                fen: None,
-                time: None,
-                inc: None,
-            };
-            let start_package_bytes: Vec<u8> = start_package.try_into().unwrap();
-
-            if let Some(stream) = self.client_stream.as_mut() {
-                match stream.write_all(&start_package_bytes) {
-                    Ok(_) => match stream.flush() {
-                        Ok(_) => println!("Stream flushed successfully"),
-                        Err(e) => println!("Error flushing stream: {}", e),
-                    },
-                    Err(e) => println!("Error sending init game package: {}", e),
-                }
+        self.piece_images = load_piece_images(ctx, &self.board);
+        if let Some(clock) = self.clock.as_mut() {
+            if self.board.get_game_result() == ChessResult::InProgress {
+                clock.press(mover);
             } else {
-                println!("No client stream found");
+                clock.stop();
             }
         }
+        if self.board.get_game_result() != ChessResult::InProgress {
+            self.phase = Phase::GameOver;
+        }
         Ok(())
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        let mut canvas: graphics::Canvas =
-            graphics::Canvas::from_frame(ctx, graphics::Color::from([0.1, 0.2, 0.3, 1.0]));
-        let color = if self.board.side() == PieceColor::Black {
-            "Black"
+    // starts a game without a network peer, from the FEN and time control in the pre-game ui
+    fn start_offline_game(&mut self, ctx: &mut Context, game_mode: GameMode) {
+        let fen = self.fen_input.clone();
+        if let Err(e) = self.reset_board(ctx, Some(fen)) {
+            self.error_message = Some(e);
+            return;
+        }
+        self.error_message = None;
+        self.game_mode = game_mode;
+        self.phase = Phase::InGame;
+        self.offer_draw_received = false;
+        self.offer_draw_sent = false;
+        let time_control = TIME_CONTROLS[self.selected_time_control].0;
+        self.start_clock(
+            time_control.map(|(time, _)| time),
+            time_control.map(|(_, inc)| inc),
+        );
+        self.orient_board();
+    }
+
+    // plays the selected piece's move to `square` if it has one, a pawn reaching the last rank
+    // has one legal move per promotion piece so those open the promotion dialog
+    fn move_selected_piece_to(&mut self, ctx: &mut Context, square: Square) -> bool {
+        let selected_moves: Vec<Move> = self
+            .current_legal_moves
+            .iter()
+            .flatten()
+            .filter(|m| m.to() == square)
+            .copied()
+            .collect();
+
+        if selected_moves.len() > 1 {
+            self.open_promotion_dialog(ctx, selected_moves);
+            true
+        } else if let Some(selected_move) = selected_moves.first() {
+            self.submit_move(ctx, *selected_move);
+            true
         } else {
-            "White"
-        };
-        draw_color_picker(&mut canvas, ctx);
-        // Draw an image.
-        //canvas.draw(&self.image, graphics::DrawParam::new().dest(dst));
-        if self.start.is_some() {
-            let forfeit_text = Text::new("Forfeit");
-            let offer_draw_text = Text::new("Offer draw");
+            false
+        }
+    }
 
-            forfeit_text.draw(
-                &mut canvas,
-                graphics::DrawParam::new()
-                    .dest(glam::Vec2::new(820.0, 60.0))
-                    .z(100),
-            );
-            offer_draw_text.draw(
-                &mut canvas,
-                graphics::DrawParam::new()
-                    .dest(glam::Vec2::new(810.0, 110.0))
-                    .color(graphics::Color::BLACK)
-                    .z(100),
-            );
-            let forfeit_rect = graphics::Mesh::new_rounded_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                graphics::Rect::new(800.0, 50.0, 100.0, 40.0),
-                5.0,
-                graphics::Color::new(0.0, 0.0, 1.0, 1.0),
-            )?;
+    // drops the selected piece along with its legal moves, an open promotion dialog and a drag
+    fn clear_selection(&mut self) {
+        self.current_legal_moves = None;
+        self.selected_square = None;
+        self.promotion_choices = None;
+        self.drag_square = None;
+        self.drag_position = None;
+    }
 
-            let offer_draw_rect = graphics::Mesh::new_rounded_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                graphics::Rect::new(800.0, 100.0, 100.0, 40.0),
-                5.0,
-                graphics::Color::new(0.0, 1.0, 0.0, 1.0),
-            )?;
+    // turns the board so the player's own pieces are at the bottom, white in a local game
+    pub fn orient_board(&mut self) {
+        let bottom = match self.game_mode {
+            // both sides play at this screen, so the side to move says nothing about who sits where
+            GameMode::Local => Some(PieceColor::White),
+            _ => self.player_color(),
+        };
+        self.flipped = bottom == Some(PieceColor::Black);
+    }
 
-            forfeit_rect.draw(&mut canvas, graphics::DrawParam::default());
-            offer_draw_rect.draw(&mut canvas, graphics::DrawParam::default());
+    // sends our move to the peer, it is played once the peer has acknowledged it
+    // in local and computer games there is no one to ask so it is played right away
+    pub fn submit_move(&mut self, ctx: &mut Context, selected_move: Move) {
+        if self.phase != Phase::InGame {
+            self.clear_selection();
+            return;
         }
-        if self.game_has_ended && self.board.get_game_result() == ChessResult::InProgress {
-            Text::new("Game has ended. Press restart to start new game.").draw(
-                &mut canvas,
-                graphics::DrawParam::new().dest(glam::Vec2::new(350.0, 40.0)),
-            );
-        } else {
-            let status_text: Text = match self.board.get_game_result() {
-            ChessResult::Checkmate {
-                winner: PieceColor::White,
-            } => Text::new("Black in checkmate, white has won. Press restart to start new game."),
-            ChessResult::Checkmate {
-                winner: PieceColor::Black,
-            } => Text::new("White in checkmate, black has won. Press restart to start new game"),
-            ChessResult::Draw => {
-                Text::new("Game has been drawed. Press restart to start new game.")
+        println!("Selected move: {:?}", selected_move);
+        if self.game_mode != GameMode::Network {
+            if let Err(e) = self.apply_move(ctx, selected_move) {
+                println!("Error making move: {}", e);
             }
-            ChessResult::FiftyMoveRule => Text::new(
-                "Game has been drawed due to 50 move rule. Press restart to start new game",
+            self.current_legal_moves = None;
+            self.selected_square = None;
+            return;
+        }
+        self.pending_chess_move = Some(selected_move);
+        let network_move = NetworkMove {
+            from: (
+                selected_move.from().file.to_idx(),
+                selected_move.from().rank.to_idx(),
             ),
-            ChessResult::ThreefoldRepetition => Text::new(
-                "Game has been drawed due to three fold repition. Press restart to start new game",
+            to: (
+                selected_move.to().file.to_idx(),
+                selected_move.to().rank.to_idx(),
             ),
-            ChessResult::Stalemate => {
-                Text::new("Game has been drawed due to stalemate. Press restart to start new game")
-            }
-            ChessResult::InProgress => Text::new("Game in progress"),
+            forfeit: false,
+            offer_draw: false,
+            promotion: piece_to_promotion_piece(selected_move.promotion()),
         };
+        send_package(self, Packet::Move(network_move));
 
-            status_text.draw(
-                &mut canvas,
-                graphics::DrawParam::new().dest(glam::Vec2::new(400.0, 40.0)),
-            );
+        self.current_legal_moves = None;
+        self.selected_square = None;
+    }
+
+    fn open_promotion_dialog(&mut self, ctx: &mut Context, moves: Vec<Move>) {
+        // the choices are shown in the colour of the pawn that is promoting
+        let Some((color, _)) = moves.first().and_then(|m| self.board.get_piece(m.from())) else {
+            return;
+        };
+        let choices = PROMOTION_PIECES
+            .iter()
+            .filter_map(|piece| {
+                moves
+                    .iter()
+                    .find(|m| m.promotion() == Some(*piece))
+                    .map(|m| {
+                        let image =
+                            graphics::Image::from_path(ctx, piece_to_image((*piece, color)))
+                                .unwrap();
+                        (*m, image)
+                    })
+            })
+            .collect();
+        self.promotion_choices = Some(choices);
+    }
+
+    // starts a search when it is the computer's turn and plays the move once it is found
+    fn update_computer_opponent(&mut self, ctx: &mut Context) {
+        let GameMode::Computer { computer_color } = self.game_mode else {
+            return;
+        };
+        if let Some(search) = &self.ai_search {
+            match search.try_recv() {
+                Ok(Some(chess_move)) => {
+                    self.ai_search = None;
+                    if self.phase == Phase::InGame {
+                        if let Err(e) = self.apply_move(ctx, chess_move) {
+                            println!("Error making move: {}", e);
+                        }
+                    }
+                }
+                Ok(None) | Err(TryRecvError::Disconnected) => self.ai_search = None,
+                Err(TryRecvError::Empty) => {}
+            }
+            return;
         }
-        // display rank and file
-        for i in 0..8 {
-            // display rank
-            let rank_text = Text::new((i + 1).to_string());
-            rank_text.draw(
-                &mut canvas,
-                graphics::DrawParam::new().dest(glam::Vec2::new(80.0, 130.0 + ((i as f32) * 80.0))),
-            );
-            // display file
-            let file_text = Text::new(((b'a' + i as u8) as char).to_string());
-            file_text.draw(
-                &mut canvas,
-                graphics::DrawParam::new()
-                    .dest(glam::Vec2::new(130.0 + ((i as f32) * 80.0), 750.0)),
-            );
+        if self.phase == Phase::InGame
+            && self.board.side() == computer_color
+            && self.board.get_game_result() == ChessResult::InProgress
+        {
+            self.ai_search = Some(ai::spawn_search(self.board.clone(), self.ai_depth));
         }
+    }
 
-        if self.board.get_game_result() == ChessResult::InProgress {
-            let side_text = Text::new(String::from(color) + " to move");
-            side_text.draw(
-                &mut canvas,
-                graphics::DrawParam::new().dest(glam::Vec2::new(400.0, 70.0)),
-            );
+    // the engine goes by its latest score, the built-in evaluation stands in before it has one and
+    // for the built-in computer
+    fn computer_is_worse_off(&self, computer_color: PieceColor) -> bool {
+        let (score, side) = match (&self.game_mode, self.engine_evaluation) {
+            (GameMode::Engine { .. }, Some((UciScore::Centipawns(cp), side, _))) => (cp, side),
+            (GameMode::Engine { .. }, Some((UciScore::Mate(moves), side, _))) => (moves, side),
+            _ => (ai::evaluate(&self.board), self.board.side()),
+        };
+        // both scores are from the point of view of the side to move
+        if side == computer_color {
+            score < 0
+        } else {
+            score > 0
         }
+    }
 
-        // restart button
-        if self.selected_color.is_some() {
-            draw_restart_button(&mut canvas, ctx, self.is_host);
+    // starts the engine process if it isn't running yet, errors are shown on screen
+    fn ensure_engine(&mut self) -> bool {
+        if self.engine.is_none() {
+            match UciEngine::spawn(self.engine_path_input.trim()) {
+                Ok(engine) => {
+                    self.engine = Some(engine);
+                    self.error_message = None;
+                }
+                Err(e) => {
+                    self.error_message = Some(format!(
+                        "Could not start engine {}: {}",
+                        self.engine_path_input.trim(),
+                        e
+                    ));
+                }
+            }
         }
+        self.engine.is_some()
+    }
 
-        // Draw accept and reject buttons for draw offer
-        if self.offer_draw_received {
-            let accept_button = graphics::Mesh::new_rounded_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                graphics::Rect::new(100.0, 60.0, 100.0, 40.0),
-                5.0,
-                graphics::Color::new(0.0, 0.8, 0.0, 1.0),
-            )?;
-            let reject_button = graphics::Mesh::new_rounded_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                graphics::Rect::new(220.0, 60.0, 100.0, 40.0),
-                5.0,
-                graphics::Color::new(0.8, 0.0, 0.0, 1.0),
-            )?;
+    // plays the engine's moves when playing against it and keeps the analysis up to date
+    fn update_engine(&mut self, ctx: &mut Context) {
+        let events = match self.engine.as_mut() {
+            Some(engine) => engine.poll(),
+            None => return,
+        };
+        for event in events {
+            match event {
+                UciEvent::Info { depth, score } => {
+                    if let Some((_, side)) = self.engine_searched {
+                        self.engine_evaluation = Some((score, side, depth));
+                    }
+                }
+                UciEvent::BestMove(best_move) => {
+                    let searched = self.engine_searched.take();
+                    if let GameMode::Engine { engine_color } = self.game_mode {
+                        // only play the move if the position hasn't changed since the search began
+                        if searched == Some((self.past_moves.len(), engine_color))
+                            && self.board.side() == engine_color
+                            && self.phase == Phase::InGame
+                        {
+                            match uci_to_move(&self.board, &best_move) {
+                                Some(chess_move) => {
+                                    if let Err(e) = self.apply_move(ctx, chess_move) {
+                                        println!("Error making move: {}", e);
+                                    }
+                                }
+                                None => println!("Engine sent an illegal move: {}", best_move),
+                            }
+                        }
+                    }
+                }
+                UciEvent::Closed => {
+                    self.engine = None;
+                    self.engine_searched = None;
+                    self.error_message = Some(String::from("The engine has stopped"));
+                    return;
+                }
+            }
+        }
 
-            accept_button.draw(&mut canvas, graphics::DrawParam::default());
-            reject_button.draw(&mut canvas, graphics::DrawParam::default());
+        let position = (self.past_moves.len(), self.board.side());
+        let start_fen = self.start_fen.clone();
+        let moves = self.move_history();
+        let engine_to_move = match self.game_mode {
+            GameMode::Engine { engine_color } => {
+                self.phase == Phase::InGame
+                    && self.board.side() == engine_color
+                    && self.board.get_game_result() == ChessResult::InProgress
+            }
+            _ => false,
+        };
+        let analyse = self.analysis_enabled
+            && !matches!(self.game_mode, GameMode::Engine { .. })
+            && self.engine_searched != Some(position);
+        let engine = self.engine.as_mut().unwrap();
 
-            Text::new("Accept").draw(
-                &mut canvas,
-                graphics::DrawParam::new().dest(glam::Vec2::new(110.0, 70.0)),
-            );
-            Text::new("Reject").draw(
-                &mut canvas,
-                graphics::DrawParam::new().dest(glam::Vec2::new(250.0, 70.0)),
-            );
+        let result = if engine_to_move && !engine.is_searching() {
+            self.engine_searched = Some(position);
+            engine
+                .set_position(start_fen.as_deref(), &moves)
+                .and_then(|_| engine.go_movetime(ENGINE_MOVE_TIME))
+        } else if analyse {
+            // the stopped search still answers, its bestmove is filtered out by the engine driver
+            self.engine_searched = Some(position);
+            engine
+                .stop()
+                .and_then(|_| engine.set_position(start_fen.as_deref(), &moves))
+                .and_then(|_| engine.go_infinite())
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            println!("Error talking to engine: {}", e);
+            self.engine = None;
+            self.engine_searched = None;
+            self.error_message = Some(format!("Lost connection to the engine: {}", e));
         }
+    }
 
-        for (index, piece_move) in self.past_moves.iter().enumerate() {
-            let text_position = glam::Vec2::new(1000.0, 105.0 + ((index as f32) * 40.0));
-            let circle_position = glam::Vec2::new(980.0, 110.0 + ((index as f32) * 40.0));
-            let circle_color = if piece_move.0 == PieceColor::Black {
-                Color::new(1.0, 1.0, 1.0, 1.0)
-            } else {
-                Color::new(0.0, 0.0, 0.0, 1.0)
-            };
-
-            let color_circle = graphics::Mesh::new_circle(
+    // the pgn result tag, "*" while the game is still going
+    fn pgn_result(&self) -> &'static str {
+        let winner = match self.board.get_game_result() {
+            ChessResult::Checkmate { winner } => winner,
+            ChessResult::InProgress => match self.end_reason {
+                Some(EndReason::Timeout(loser)) | Some(EndReason::Forfeit(loser)) => {
+                    opposite_color(loser)
+                }
+                Some(EndReason::DrawAgreed) => return "1/2-1/2",
+                None => return "*",
+            },
+            _ => return "1/2-1/2",
+        };
+        match winner {
+            PieceColor::White => "1-0",
+            PieceColor::Black => "0-1",
+        }
+    }
+
+    // white and black names for the pgn headers, "?" when a name isn't known
+    fn player_names(&self) -> (String, String) {
+        let (own_name, opponent_name) = match self.game_mode {
+            GameMode::Network => match self.is_host {
+                Some(true) => (None, self.opponent_name.clone()),
+                _ => (
+                    self.start.as_ref().and_then(|start| start.name.clone()),
+                    None,
+                ),
+            },
+            GameMode::Local => (None, None),
+            GameMode::Computer { .. } => (None, Some(String::from("Computer"))),
+            GameMode::Engine { .. } => (None, Some(self.engine_path_input.trim().to_string())),
+        };
+        let own_name = own_name.unwrap_or_else(|| String::from("?"));
+        let opponent_name = opponent_name.unwrap_or_else(|| String::from("?"));
+        if self.player_color() == Some(PieceColor::Black) {
+            (opponent_name, own_name)
+        } else {
+            (own_name, opponent_name)
+        }
+    }
+
+    // writes the current game to a pgn file and tells the player where it went
+    pub fn export_game(&mut self) {
+        let (white, black) = self.player_names();
+        let headers = PgnHeaders {
+            event: String::from(match self.game_mode {
+                GameMode::Network => "Network game",
+                GameMode::Local => "Local game",
+                GameMode::Computer { .. } | GameMode::Engine { .. } => "Computer game",
+            }),
+            site: String::from("?"),
+            date: pgn_date_today(),
+            round: String::from("-"),
+            white,
+            black,
+            result: self.pgn_result().to_string(),
+        };
+        let result = export_pgn(&headers, self.start_fen.as_deref(), &self.move_history())
+            .and_then(|pgn| save_pgn(&pgn));
+        match result {
+            Ok(file_name) => {
+                println!("Saved game to {}", file_name);
+                self.info_message = Some(format!("Saved game to {}", file_name));
+            }
+            Err(e) => self.error_message = Some(e),
+        }
+    }
+
+    // opens a pgn file in the replay viewer, starting from the game's first position
+    fn load_replay(&mut self, ctx: &mut Context) {
+        let game = match load_pgn(self.pgn_path_input.trim()) {
+            Ok(game) => game,
+            Err(e) => {
+                self.error_message = Some(e);
+                return;
+            }
+        };
+        if let Err(e) = self.reset_board(ctx, game.start_fen().map(String::from)) {
+            self.error_message = Some(e);
+            return;
+        }
+        self.error_message = None;
+        self.replay = Some(game);
+        self.show_replay_ply(ctx, 0);
+    }
+
+    // shows the replayed game after `ply` moves, rebuilding the board from the start position
+    fn show_replay_ply(&mut self, ctx: &mut Context, ply: usize) {
+        let Some(replay) = &self.replay else {
+            return;
+        };
+        let mut board = match board_from_fen(replay.start_fen()) {
+            Ok(board) => board,
+            Err(e) => {
+                self.error_message = Some(e);
+                return;
+            }
+        };
+        let ply = ply.min(replay.moves.len());
+        let mut past_moves = vec![];
+        for pgn_move in &replay.moves[..ply] {
+            let san = move_to_san(&board, pgn_move.chess_move);
+            if let Err(e) = board.make_move(pgn_move.chess_move) {
+                println!("Error replaying move {}: {}", pgn_move.san, e);
+                break;
+            }
+            past_moves.push(PastMove {
+                chess_move: pgn_move.chess_move,
+                san,
+            });
+        }
+        self.replay_ply = past_moves.len();
+        self.past_moves = past_moves;
+        self.piece_images = load_piece_images(ctx, &board);
+        self.board = board;
+        self.scroll_to_ply(self.replay_ply);
+    }
+
+    // the number of rows in the move list, a game starting with black takes one extra row
+    fn move_list_row_count(&self) -> usize {
+        let (_, white_first) = first_move_number(self.start_fen.as_deref());
+        let offset = if white_first { 0 } else { 1 };
+        (self.past_moves.len() + offset).div_ceil(2)
+    }
+
+    fn max_move_list_scroll(&self) -> usize {
+        self.move_list_row_count().saturating_sub(MOVE_LIST_ROWS)
+    }
+
+    // scrolls the move list just far enough for the move leading to `ply` to be visible
+    fn scroll_to_ply(&mut self, ply: usize) {
+        let (_, white_first) = first_move_number(self.start_fen.as_deref());
+        let offset = if white_first { 0 } else { 1 };
+        let row = (ply + offset).saturating_sub(1) / 2;
+        if row < self.move_list_scroll {
+            self.move_list_scroll = row;
+        } else if row >= self.move_list_scroll + MOVE_LIST_ROWS {
+            self.move_list_scroll = row + 1 - MOVE_LIST_ROWS;
+        }
+        self.move_list_scroll = self.move_list_scroll.min(self.max_move_list_scroll());
+    }
+
+    // the number of moves played in the position reached by the move clicked in the list
+    fn move_list_ply_at(&self, x: f32, y: f32) -> Option<usize> {
+        if !move_list_contains(x, y) {
+            return None;
+        }
+        let (_, white_first) = first_move_number(self.start_fen.as_deref());
+        let offset = if white_first { 0 } else { 1 };
+        let row = self.move_list_scroll + ((y - MOVE_LIST_Y) / MOVE_LIST_ROW_HEIGHT) as usize;
+        // white's move sits in the middle column and black's in the right one
+        let ply = if x >= MOVE_LIST_BLACK_X {
+            2 * row + 2 - offset
+        } else if x >= MOVE_LIST_WHITE_X {
+            (2 * row + 1).checked_sub(offset)?
+        } else {
+            return None;
+        };
+        if (1..=self.past_moves.len()).contains(&ply) {
+            Some(ply)
+        } else {
+            None
+        }
+    }
+
+    // the position after the first `ply` moves of the current game
+    fn board_at_ply(&self, ply: usize) -> Result<Board, String> {
+        let mut board = board_from_fen(self.start_fen.as_deref())?;
+        for past_move in self.past_moves.iter().take(ply) {
+            board
+                .make_move(past_move.chess_move)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(board)
+    }
+
+    // shows an earlier position on the board without touching the game being played
+    fn view_ply(&mut self, ctx: &mut Context, ply: usize) {
+        if ply >= self.past_moves.len() {
+            self.back_to_live();
+            return;
+        }
+        match self.board_at_ply(ply) {
+            Ok(board) => {
+                self.viewed_images = Some(load_piece_images(ctx, &board));
+                self.viewed_board = Some(board);
+                self.viewing_ply = Some(ply);
+                self.current_legal_moves = None;
+                self.selected_square = None;
+            }
+            Err(e) => self.error_message = Some(e),
+        }
+    }
+
+    fn back_to_live(&mut self) {
+        self.viewing_ply = None;
+        self.viewed_board = None;
+        self.viewed_images = None;
+        self.scroll_to_ply(self.past_moves.len());
+    }
+
+    // every button that is on screen right now, used both to draw them and to find what was clicked
+    fn buttons(&self) -> Vec<(UiAction, Button)> {
+        match self.phase {
+            Phase::Menu => self.menu_buttons(),
+            Phase::Lobby => self.lobby_buttons(),
+            Phase::InGame | Phase::GameOver => self.game_buttons(),
+            Phase::Disconnected => self.disconnected_buttons(),
+        }
+    }
+
+    // color and time control, picked by whoever sends the start package
+    fn setup_buttons(&self) -> Vec<(UiAction, Button)> {
+        let mut buttons = vec![
+            (
+                UiAction::ChooseColor(PieceColor::White),
+                Button::new(graphics::Rect::new(500.0, 800.0, 60.0, 40.0), "")
+                    .with_color(graphics::Color::WHITE),
+            ),
+            (
+                UiAction::ChooseColor(PieceColor::Black),
+                Button::new(graphics::Rect::new(500.0, 850.0, 60.0, 40.0), "")
+                    .with_color(graphics::Color::BLACK),
+            ),
+        ];
+        for (index, (_, name)) in TIME_CONTROLS.iter().enumerate() {
+            let rect = graphics::Rect::new(360.0, 800.0 + (index as f32 * 30.0), 110.0, 25.0);
+            let color = if index == self.selected_time_control {
+                graphics::Color::new(0.0, 0.6, 0.0, 1.0)
+            } else {
+                graphics::Color::new(0.4, 0.4, 0.4, 1.0)
+            };
+            buttons.push((
+                UiAction::TimeControl(index),
+                Button::new(rect, *name).with_color(color),
+            ));
+        }
+        buttons
+    }
+
+    fn analysis_button(&self) -> (UiAction, Button) {
+        let (label, color) = if self.analysis_enabled {
+            ("Analysis: on", graphics::Color::new(0.0, 0.6, 0.0, 1.0))
+        } else {
+            ("Analysis: off", graphics::Color::new(0.4, 0.4, 0.4, 1.0))
+        };
+        (
+            UiAction::ToggleAnalysis,
+            Button::new(graphics::Rect::new(820.0, 1000.0, 150.0, 40.0), label).with_color(color),
+        )
+    }
+
+    // only offered while an earlier position is being looked at
+    fn back_to_live_button(&self) -> Option<(UiAction, Button)> {
+        self.viewing_ply.map(|_| {
+            (
+                UiAction::BackToLive,
+                Button::new(
+                    graphics::Rect::new(MOVE_LIST_X, 710.0, 140.0, 30.0),
+                    "Back to live",
+                ),
+            )
+        })
+    }
+
+    fn menu_buttons(&self) -> Vec<(UiAction, Button)> {
+        let grey = graphics::Color::new(0.4, 0.4, 0.4, 1.0);
+        let mut buttons = self.setup_buttons();
+        if self.selected_color.is_some() {
+            buttons.push((
+                UiAction::Host,
+                Button::new(
+                    graphics::Rect::new(640.0, 800.0, 150.0, 40.0),
+                    "New game + (host)",
+                ),
+            ));
+            buttons.push((
+                UiAction::Join,
+                Button::new(
+                    graphics::Rect::new(640.0, 850.0, 150.0, 40.0),
+                    "New game + (join)",
+                ),
+            ));
+        }
+        buttons.push((
+            UiAction::LoadPgn,
+            Button::new(graphics::Rect::new(420.0, 1130.0, 120.0, 30.0), "Load PGN"),
+        ));
+        buttons.push((
+            UiAction::LocalGame,
+            Button::new(graphics::Rect::new(820.0, 800.0, 150.0, 40.0), "Local game"),
+        ));
+        buttons.push((
+            UiAction::ComputerGame,
+            Button::new(
+                graphics::Rect::new(820.0, 850.0, 150.0, 40.0),
+                "vs Computer",
+            ),
+        ));
+        buttons.push((
+            UiAction::DepthDown,
+            Button::new(graphics::Rect::new(820.0, 900.0, 40.0, 30.0), "-")
+                .with_color(grey)
+                .with_enabled(self.ai_depth > ai::MIN_DEPTH),
+        ));
+        buttons.push((
+            UiAction::DepthUp,
+            Button::new(graphics::Rect::new(930.0, 900.0, 40.0, 30.0), "+")
+                .with_color(grey)
+                .with_enabled(self.ai_depth < ai::MAX_DEPTH),
+        ));
+        buttons.push((
+            UiAction::EngineGame,
+            Button::new(graphics::Rect::new(820.0, 950.0, 150.0, 40.0), "vs Engine"),
+        ));
+        buttons.push(self.analysis_button());
+
+        // first, back, forward, last and close, in a row under the board
+        if let Some(replay) = &self.replay {
+            let at_start = self.replay_ply == 0;
+            let at_end = self.replay_ply >= replay.moves.len();
+            let controls = [
+                (UiAction::ReplayFirst, "|<", !at_start),
+                (UiAction::ReplayBack, "<", !at_start),
+                (UiAction::ReplayForward, ">", !at_end),
+                (UiAction::ReplayLast, ">|", !at_end),
+                (UiAction::ReplayClose, "Close", true),
+            ];
+            for (index, (action, label, enabled)) in controls.into_iter().enumerate() {
+                let rect = graphics::Rect::new(110.0 + (index as f32 * 70.0), 770.0, 60.0, 25.0);
+                buttons.push((
+                    action,
+                    Button::new(rect, label)
+                        .with_color(graphics::Color::new(0.5, 0.5, 0.5, 1.0))
+                        .with_enabled(enabled),
+                ));
+            }
+        }
+        buttons
+    }
+
+    fn lobby_buttons(&self) -> Vec<(UiAction, Button)> {
+        // while waiting for an opponent only cancelling is possible
+        if self.listener.is_some() {
+            return vec![(
+                UiAction::LeaveLobby,
+                Button::new(graphics::Rect::new(370.0, 420.0, 100.0, 40.0), "Cancel").with_z(201),
+            )];
+        }
+        // the client sets up the game, the host waits for its start package
+        let mut buttons = if self.is_host == Some(false) {
+            let mut buttons = self.setup_buttons();
+            buttons.push((
+                UiAction::InitGame,
+                Button::new(graphics::Rect::new(640.0, 800.0, 150.0, 40.0), "Init game")
+                    .with_enabled(self.selected_color.is_some()),
+            ));
+            buttons
+        } else {
+            vec![]
+        };
+        buttons.push((
+            UiAction::LeaveLobby,
+            Button::new(graphics::Rect::new(640.0, 850.0, 150.0, 40.0), "Leave"),
+        ));
+        buttons
+    }
+
+    fn game_buttons(&self) -> Vec<(UiAction, Button)> {
+        let mut buttons = vec![];
+        if self.phase == Phase::InGame {
+            buttons.push((
+                UiAction::Forfeit,
+                Button::new(graphics::Rect::new(800.0, 50.0, 100.0, 40.0), "Forfeit")
+                    .with_color(graphics::Color::new(0.0, 0.0, 1.0, 1.0)),
+            ));
+            buttons.push((
+                UiAction::OfferDraw,
+                Button::new(graphics::Rect::new(800.0, 100.0, 100.0, 40.0), "Offer draw")
+                    .with_color(graphics::Color::new(0.0, 1.0, 0.0, 1.0))
+                    .with_text_color(graphics::Color::BLACK)
+                    .with_enabled(!self.offer_draw_sent),
+            ));
+            if self.offer_draw_received {
+                buttons.push((
+                    UiAction::AcceptDraw,
+                    Button::new(graphics::Rect::new(100.0, 60.0, 100.0, 40.0), "Accept")
+                        .with_color(graphics::Color::new(0.0, 0.8, 0.0, 1.0)),
+                ));
+                buttons.push((
+                    UiAction::RejectDraw,
+                    Button::new(graphics::Rect::new(220.0, 60.0, 100.0, 40.0), "Reject")
+                        .with_color(graphics::Color::new(0.8, 0.0, 0.0, 1.0)),
+                ));
+            }
+        } else {
+            buttons.push((
+                UiAction::BackToMenu,
+                Button::new(graphics::Rect::new(800.0, 50.0, 100.0, 40.0), "Restart"),
+            ));
+        }
+        buttons.push((
+            UiAction::ExportPgn,
+            Button::new(graphics::Rect::new(910.0, 50.0, 120.0, 40.0), "Export PGN")
+                .with_color(graphics::Color::new(0.5, 0.5, 0.5, 1.0)),
+        ));
+        buttons.push(self.analysis_button());
+        buttons.extend(self.back_to_live_button());
+        buttons
+    }
+
+    fn disconnected_buttons(&self) -> Vec<(UiAction, Button)> {
+        // the host waits for the opponent to connect again, the client connects to the host
+        let mut buttons = if self.connection.is_some() {
+            vec![]
+        } else if self.listener.is_some() {
+            vec![(
+                UiAction::CancelReconnect,
+                Button::new(graphics::Rect::new(370.0, 420.0, 100.0, 40.0), "Cancel").with_z(201),
+            )]
+        } else {
+            let label = if self.is_host == Some(true) {
+                "Wait for opponent"
+            } else {
+                "Reconnect"
+            };
+            vec![(
+                UiAction::Reconnect,
+                Button::new(graphics::Rect::new(800.0, 100.0, 150.0, 40.0), label),
+            )]
+        };
+        buttons.push((
+            UiAction::BackToMenu,
+            Button::new(graphics::Rect::new(800.0, 50.0, 100.0, 40.0), "Leave game"),
+        ));
+        buttons.push((
+            UiAction::ExportPgn,
+            Button::new(graphics::Rect::new(910.0, 50.0, 120.0, 40.0), "Save game")
+                .with_color(graphics::Color::new(0.5, 0.5, 0.5, 1.0)),
+        ));
+        buttons.extend(self.back_to_live_button());
+        buttons
+    }
+
+    // the text fields of the menu, the client's lobby keeps the one for the start position
+    fn text_inputs(&self) -> Vec<(TextInputField, TextInput)> {
+        let inputs = vec![
+            (
+                TextInputField::Fen,
+                TextInput::new(
+                    graphics::Rect::new(100.0, 980.0, 690.0, 30.0),
+                    "Start position (FEN, empty = standard)",
+                ),
+            ),
+            (
+                TextInputField::Address,
+                TextInput::new(
+                    graphics::Rect::new(100.0, 1070.0, 300.0, 30.0),
+                    "Host / join address",
+                ),
+            ),
+            (
+                TextInputField::EnginePath,
+                TextInput::new(
+                    graphics::Rect::new(420.0, 1070.0, 370.0, 30.0),
+                    "UCI engine",
+                ),
+            ),
+            (
+                TextInputField::PgnPath,
+                TextInput::new(graphics::Rect::new(100.0, 1130.0, 300.0, 30.0), "PGN file"),
+            ),
+            (
+                TextInputField::Timeout,
+                TextInput::new(
+                    graphics::Rect::new(560.0, 1130.0, 100.0, 30.0),
+                    "Timeout (s)",
+                ),
+            ),
+        ];
+        match self.phase {
+            Phase::Menu => inputs,
+            Phase::Lobby if self.listener.is_none() && self.is_host == Some(false) => inputs
+                .into_iter()
+                .filter(|(field, _)| *field == TextInputField::Fen)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    fn handle_action(&mut self, ctx: &mut Context, action: UiAction) {
+        // only what the current phase offers can be done
+        if !self
+            .buttons()
+            .iter()
+            .any(|(available, button)| *available == action && button.enabled)
+        {
+            return;
+        }
+        match action {
+            UiAction::Host => {
+                // the board is set up again from the client's start package
+                self.reset_board(ctx, None).unwrap();
+                self.game_mode = GameMode::Network;
+                let address = self.address_input.trim().to_string();
+                match listen_for_connections(address, self) {
+                    Ok(_) => {
+                        self.error_message = None;
+                        self.phase = Phase::Lobby;
+                    }
+                    Err(e) => self.error_message = Some(e),
+                }
+            }
+            UiAction::Join => {
+                // the board is set up again when the game is initialised
+                self.reset_board(ctx, None).unwrap();
+                self.game_mode = GameMode::Network;
+                let address = self.address_input.trim().to_string();
+                match connect_to_host(address, self) {
+                    Ok(_) => {
+                        self.error_message = None;
+                        self.phase = Phase::Lobby;
+                    }
+                    Err(e) => self.error_message = Some(e),
+                }
+            }
+            UiAction::InitGame => {
+                if let Err(e) = board_from_fen(Some(&self.fen_input)) {
+                    self.error_message = Some(e);
+                    return;
+                }
+                self.error_message = None;
+                let fen = self.fen_input.trim().to_string();
+                let start_package = Start {
+                    is_white: self.selected_color == Some(PieceColor::White),
+                    name: Some("Alexander".to_string()),
+                    fen: if fen.is_empty() { None } else { Some(fen) },
+                    time: TIME_CONTROLS[self.selected_time_control]
+                        .0
+                        .map(|(time, _)| time),
+                    inc: TIME_CONTROLS[self.selected_time_control]
+                        .0
+                        .map(|(_, inc)| inc),
+                };
+                send_package(self, Packet::Start(start_package));
+            }
+            UiAction::LeaveLobby => self.leave_lobby(),
+            UiAction::Reconnect => {
+                let address = self.address_input.trim().to_string();
+                let result = if self.is_host == Some(true) {
+                    listen_for_connections(address, self)
+                } else {
+                    connect_to_host(address, self).map(|_| self.request_resume())
+                };
+                match result {
+                    Ok(_) => self.error_message = None,
+                    Err(e) => self.error_message = Some(e),
+                }
+            }
+            UiAction::CancelReconnect => {
+                self.listener = None;
+                println!("Stopped waiting for the opponent");
+            }
+            UiAction::BackToMenu => self.back_to_menu(),
+            UiAction::ChooseColor(color) => self.selected_color = Some(color),
+            UiAction::TimeControl(index) => self.selected_time_control = index,
+            UiAction::LocalGame => self.start_offline_game(ctx, GameMode::Local),
+            // the computer and the engine take the color not chosen
+            UiAction::ComputerGame => {
+                let player_color = self.selected_color.unwrap_or(PieceColor::White);
+                self.start_offline_game(
+                    ctx,
+                    GameMode::Computer {
+                        computer_color: opposite_color(player_color),
+                    },
+                );
+            }
+            UiAction::EngineGame => {
+                let player_color = self.selected_color.unwrap_or(PieceColor::White);
+                if self.ensure_engine() {
+                    self.start_offline_game(
+                        ctx,
+                        GameMode::Engine {
+                            engine_color: opposite_color(player_color),
+                        },
+                    );
+                }
+            }
+            UiAction::DepthDown => self.ai_depth = (self.ai_depth - 1).max(ai::MIN_DEPTH),
+            UiAction::DepthUp => self.ai_depth = (self.ai_depth + 1).min(ai::MAX_DEPTH),
+            UiAction::ToggleAnalysis => {
+                if self.analysis_enabled {
+                    self.analysis_enabled = false;
+                    self.engine_evaluation = None;
+                    if let Some(engine) = self.engine.as_mut() {
+                        if let Err(e) = engine.stop() {
+                            println!("Error stopping engine: {}", e);
+                        }
+                    }
+                } else if self.ensure_engine() {
+                    self.analysis_enabled = true;
+                    self.engine_searched = None;
+                }
+            }
+            UiAction::Forfeit => {
+                if let Some(color) = self.player_color() {
+                    self.end_game(EndReason::Forfeit(color));
+                }
+                if self.game_mode == GameMode::Network {
+                    send_forfeit(self);
+                }
+            }
+            UiAction::OfferDraw => match self.game_mode {
+                // both players are at the same screen, offering a draw is agreeing to it
+                GameMode::Local => self.end_game(EndReason::DrawAgreed),
+                // the computer takes the draw when it thinks it is worse off
+                GameMode::Computer {
+                    computer_color: color,
+                }
+                | GameMode::Engine {
+                    engine_color: color,
+                } => {
+                    if self.computer_is_worse_off(color) {
+                        self.end_game(EndReason::DrawAgreed);
+                    } else {
+                        self.info_message = Some(String::from("The computer declined the draw"));
+                    }
+                }
+                GameMode::Network => {
+                    let offer_draw_package = NetworkMove {
+                        from: (0, 0),
+                        to: (0, 0),
+                        promotion: None,
+                        forfeit: false,
+                        offer_draw: true,
+                    };
+                    self.offer_draw_sent = true;
+                    send_package(self, Packet::Move(offer_draw_package));
+                }
+            },
+            UiAction::AcceptDraw => {
+                self.offer_draw_received = false;
+                let ack_package = Ack {
+                    ok: true,
+                    end_state: Some(GameState::Draw),
+                };
+                self.end_game(EndReason::DrawAgreed);
+                send_package(self, Packet::Ack(ack_package));
+            }
+            UiAction::RejectDraw => {
+                self.offer_draw_received = false;
+                let ack_package = Ack {
+                    ok: false,
+                    end_state: None,
+                };
+                send_package(self, Packet::Ack(ack_package));
+            }
+            UiAction::ExportPgn => self.export_game(),
+            UiAction::LoadPgn => self.load_replay(ctx),
+            UiAction::ReplayFirst => self.show_replay_ply(ctx, 0),
+            UiAction::ReplayBack => self.show_replay_ply(ctx, self.replay_ply.saturating_sub(1)),
+            UiAction::ReplayForward => self.show_replay_ply(ctx, self.replay_ply + 1),
+            UiAction::ReplayLast => {
+                let last = self.replay.as_ref().map_or(0, |replay| replay.moves.len());
+                self.show_replay_ply(ctx, last);
+            }
+            UiAction::ReplayClose => {
+                self.reset_board(ctx, None).unwrap();
+            }
+            UiAction::BackToLive => self.back_to_live(),
+        }
+    }
+
+    // the labels of the color and time control pickers
+    fn draw_setup_labels(&self, canvas: &mut graphics::Canvas) {
+        Label::new(Vec2::new(500.0, 780.0), "Choose color").draw(canvas);
+        Label::new(Vec2::new(360.0, 780.0), "Time control").draw(canvas);
+    }
+
+    fn draw_menu(&self, canvas: &mut graphics::Canvas) {
+        if let Some(replay) = &self.replay {
+            draw_replay_info(canvas, replay, self.replay_ply);
+        }
+        self.draw_setup_labels(canvas);
+        Label::new(Vec2::new(866.0, 907.0), format!("Depth {}", self.ai_depth)).draw(canvas);
+    }
+
+    fn draw_lobby(&self, canvas: &mut graphics::Canvas, ctx: &mut Context) -> GameResult {
+        if self.listener.is_some() {
+            Panel::new(
+                graphics::Rect::new(BOARD_X, BOARD_Y, BOARD_SIZE, BOARD_SIZE),
+                graphics::Color::new(0.0, 0.0, 0.0, 0.7),
+            )
+            .with_z(200)
+            .draw(canvas, ctx)?;
+            Label::new(
+                Vec2::new(250.0, 380.0),
+                format!("Waiting for opponent on {}…", self.address_input.trim()),
+            )
+            .with_z(201)
+            .draw(canvas);
+        } else if self.is_host == Some(false) {
+            self.draw_setup_labels(canvas);
+            Label::new(
+                Vec2::new(350.0, 40.0),
+                "Connected, choose the game and press init game",
+            )
+            .draw(canvas);
+        } else {
+            Label::new(
+                Vec2::new(350.0, 40.0),
+                "Connected, waiting for the opponent to start the game",
+            )
+            .draw(canvas);
+        }
+        Ok(())
+    }
+
+    fn draw_in_game(&self, canvas: &mut graphics::Canvas) {
+        Label::new(Vec2::new(400.0, 40.0), "Game in progress").draw(canvas);
+        let side = match self.board.side() {
+            PieceColor::White => "White",
+            PieceColor::Black => "Black",
+        };
+        Label::new(Vec2::new(400.0, 70.0), format!("{} to move", side)).draw(canvas);
+    }
+
+    fn draw_disconnected(&self, canvas: &mut graphics::Canvas, ctx: &mut Context) -> GameResult {
+        Label::new(Vec2::new(400.0, 40.0), "Opponent disconnected")
+            .with_color(graphics::Color::new(1.0, 0.3, 0.3, 1.0))
+            .draw(canvas);
+        let status = if self.connection.is_some() {
+            "Reconnected, waiting for the game to resume"
+        } else {
+            "The game is paused, save it or wait for the opponent"
+        };
+        Label::new(Vec2::new(400.0, 70.0), status).draw(canvas);
+        if self.listener.is_some() {
+            Panel::new(
+                graphics::Rect::new(BOARD_X, BOARD_Y, BOARD_SIZE, BOARD_SIZE),
+                graphics::Color::new(0.0, 0.0, 0.0, 0.7),
+            )
+            .with_z(200)
+            .draw(canvas, ctx)?;
+            Label::new(
+                Vec2::new(250.0, 380.0),
+                format!(
+                    "Waiting for the opponent to reconnect on {}…",
+                    self.address_input.trim()
+                ),
+            )
+            .with_z(201)
+            .draw(canvas);
+        }
+        Ok(())
+    }
+
+    fn draw_game_over(&self, canvas: &mut graphics::Canvas) {
+        let end_text = match self.board.get_game_result() {
+            ChessResult::Checkmate {
+                winner: PieceColor::White,
+            } => "Black in checkmate, white has won. Press restart to start new game.",
+            ChessResult::Checkmate {
+                winner: PieceColor::Black,
+            } => "White in checkmate, black has won. Press restart to start new game",
+            ChessResult::Draw => "Game has been drawed. Press restart to start new game.",
+            ChessResult::FiftyMoveRule => {
+                "Game has been drawed due to 50 move rule. Press restart to start new game"
+            }
+            ChessResult::ThreefoldRepetition => {
+                "Game has been drawed due to three fold repition. Press restart to start new game"
+            }
+            ChessResult::Stalemate => {
+                "Game has been drawed due to stalemate. Press restart to start new game"
+            }
+            // the game ended off the board
+            ChessResult::InProgress => match self.end_reason {
+                Some(EndReason::Timeout(PieceColor::White)) => {
+                    "White ran out of time, black has won. Press restart to start new game."
+                }
+                Some(EndReason::Timeout(PieceColor::Black)) => {
+                    "Black ran out of time, white has won. Press restart to start new game."
+                }
+                Some(EndReason::Forfeit(PieceColor::White)) => {
+                    "White forfeited, black has won. Press restart to start new game."
+                }
+                Some(EndReason::Forfeit(PieceColor::Black)) => {
+                    "Black forfeited, white has won. Press restart to start new game."
+                }
+                Some(EndReason::DrawAgreed) => {
+                    "Game has been drawed by agreement. Press restart to start new game."
+                }
+                None => "Game has ended. Press restart to start new game.",
+            },
+        };
+        Label::new(Vec2::new(350.0, 40.0), end_text).draw(canvas);
+    }
+
+    // goes back to the menu from the lobby, dropping the listener or the connection
+    pub fn leave_lobby(&mut self) {
+        if self.listener.take().is_some() {
+            println!("Stopped listening for connections");
+        }
+        self.connection = None;
+        self.is_host = None;
+        self.phase = Phase::Menu;
+    }
+
+    // leaves a finished game, its final position stays on the board until the next one starts
+    fn back_to_menu(&mut self) {
+        self.listener = None;
+        self.resume_history = None;
+        self.connection = None;
+        self.is_host = None;
+        self.start = None;
+        self.clock = None;
+        self.offer_draw_received = false;
+        self.offer_draw_sent = false;
+        self.back_to_live();
+        self.phase = Phase::Menu;
+    }
+
+    // the peer has gone away, a game in progress is paused until they are back or it is left
+    pub fn connection_lost(&mut self, reason: String) {
+        println!("{}", reason);
+        self.connection = None;
+        match self.phase {
+            // a game that hasn't started yet can't go on without the opponent
+            Phase::Lobby => {
+                self.leave_lobby();
+                self.error_message = Some(reason);
+            }
+            Phase::InGame => {
+                self.phase = Phase::Disconnected;
+                // a move the opponent never acknowledged isn't played
+                self.pending_chess_move = None;
+                self.clear_selection();
+                self.offer_draw_received = false;
+                self.offer_draw_sent = false;
+                if let Some(clock) = self.clock.as_mut() {
+                    clock.stop();
+                }
+                self.error_message = Some(reason);
+            }
+            Phase::Menu | Phase::Disconnected | Phase::GameOver => {}
+        }
+    }
+
+    // asks the host to carry on with the paused game, it answers with the position to continue from
+    fn request_resume(&mut self) {
+        let Some(start) = &self.start else {
+            return;
+        };
+        let start_package = Start {
+            is_white: start.is_white,
+            name: Some("Alexander".to_string()),
+            fen: start.fen.clone(),
+            time: start.time,
+            inc: start.inc,
+        };
+        send_package(self, Packet::Start(start_package));
+    }
+
+    // the opponent is back, the paused game goes on
+    pub fn connection_restored(&mut self) {
+        self.phase = Phase::InGame;
+        self.error_message = None;
+        self.info_message = Some(String::from("Opponent reconnected"));
+        if let Some(clock) = self.clock.as_mut() {
+            clock.start();
+        }
+    }
+
+    pub fn end_game(&mut self, reason: EndReason) {
+        self.phase = Phase::GameOver;
+        self.clear_selection();
+        self.end_reason = Some(reason);
+        if let Some(clock) = self.clock.as_mut() {
+            clock.stop();
+        }
+    }
+}
+
+impl event::EventHandler<ggez::GameError> for State {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        const DESIRED_FPS: u32 = 60;
+
+        poll_for_connection(self);
+        handle_incoming_packages(ctx, self);
+
+        self.update_computer_opponent(ctx);
+        self.update_engine(ctx);
+
+        let flagged = self.clock.as_ref().and_then(|clock| clock.flagged());
+        if let Some(color) = flagged {
+            if self.phase == Phase::InGame {
+                self.end_game(EndReason::Timeout(color));
+                // the side that ran out of time tells the peer it has lost
+                if self.game_mode == GameMode::Network && self.player_color() == Some(color) {
+                    send_forfeit(self);
+                }
+            }
+        }
+
+        // every finished game is saved once, whichever way it ended
+        if self.phase == Phase::GameOver && !self.pgn_saved {
+            self.pgn_saved = true;
+            self.export_game();
+        }
+        Ok(())
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        let Vec2 { x, y } = self.layout.to_design(x, y);
+        self.mouse_down = true;
+        self.focused_input = None;
+
+        // the promotion dialog is modal, clicking outside of it cancels the move
+        if let Some(choices) = self.promotion_choices.take() {
+            let chosen = choices
+                .iter()
+                .enumerate()
+                .find(|(index, _)| promotion_choice_rect(*index).contains([x, y]));
+            if let Some((_, (chosen_move, _))) = chosen {
+                self.submit_move(ctx, *chosen_move);
+            }
+            return Ok(());
+        }
+
+        let point = Vec2::new(x, y);
+        let clicked = self
+            .buttons()
+            .into_iter()
+            .find(|(_, button)| button.is_clicked(point))
+            .map(|(action, _)| action);
+        if let Some(action) = clicked {
+            self.handle_action(ctx, action);
+            return Ok(());
+        }
+        // only the cancel button can be used while waiting for an opponent
+        if self.listener.is_some() {
+            return Ok(());
+        }
+        let clicked_input = self
+            .text_inputs()
+            .into_iter()
+            .find(|(_, input)| input.contains(point))
+            .map(|(field, _)| field);
+        if let Some(field) = clicked_input {
+            if field == TextInputField::EnginePath {
+                // a new path means a new engine process the next time one is needed, analysis
+                // stops with the old one and is turned back on by hand
+                self.engine = None;
+                self.engine_searched = None;
+                self.engine_evaluation = None;
+                self.analysis_enabled = false;
+            }
+            self.focused_input = Some(field);
+            return Ok(());
+        }
+
+        if let Some(ply) = self.move_list_ply_at(x, y) {
+            if self.replay.is_some() {
+                self.show_replay_ply(ctx, ply);
+            } else {
+                self.view_ply(ctx, ply);
+            }
+            return Ok(());
+        }
+
+        // the board is read-only while an earlier position is shown or no game is being played
+        let clicked_square = square_at(x, y, self.flipped)
+            .filter(|_| self.viewing_ply.is_none() && self.phase == Phase::InGame);
+        if let Some(square) = clicked_square {
+            let file = square.file.to_idx();
+            let rank = square.rank.to_idx();
+            println!("{}, {}, x: {}, y: {}", file, rank, x, y);
+            let piece = self.board.get_piece(square);
+
+            if !self.move_selected_piece_to(ctx, square)
+                && piece.is_some_and(|p| Some(p.0) == self.player_color())
+                && self.board.side() == piece.unwrap().0
+            {
+                // We are inside the board
+                println!("Selected square: {:?}", square);
+                let legal_moves = self.board.get_moves(square);
+                println!("Legal moves: {:?}", legal_moves);
+                self.current_legal_moves = legal_moves;
+                self.selected_square = Some(square);
+                // the piece can also be dragged to where it should go
+                self.drag_square = Some(square);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        x: f32,
+        y: f32,
+        _dx: f32,
+        _dy: f32,
+    ) -> GameResult {
+        let Some(drag_square) = self.drag_square else {
+            return Ok(());
+        };
+        let Vec2 { x, y } = self.layout.to_design(x, y);
+        if self.mouse_down
+            && (self.drag_position.is_some() || square_at(x, y, self.flipped) != Some(drag_square))
+        {
+            self.drag_position = Some(Vec2::new(x, y));
+        }
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        _button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        let Vec2 { x, y } = self.layout.to_design(x, y);
+        self.mouse_down = false;
+        let drag_square = self.drag_square.take();
+        // releasing without having moved off the piece leaves it selected for click-to-move
+        if drag_square.is_some()
+            && self.drag_position.take().is_some()
+            && self.phase == Phase::InGame
+        {
+            let dropped_on = square_at(x, y, self.flipped);
+            // anywhere without a legal move puts the piece back where it came from
+            if let Some(square) = dropped_on {
+                self.move_selected_piece_to(ctx, square);
+            }
+        }
+        Ok(())
+    }
+
+    fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        self.layout = Layout::new(width, height);
+        Ok(())
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> GameResult {
+        let position = self
+            .layout
+            .to_design(ctx.mouse.position().x, ctx.mouse.position().y);
+        if move_list_contains(position.x, position.y) {
+            // scrolling up moves towards the start of the game
+            if y > 0.0 {
+                self.move_list_scroll = self.move_list_scroll.saturating_sub(3);
+            } else if y < 0.0 {
+                self.move_list_scroll =
+                    (self.move_list_scroll + 3).min(self.max_move_list_scroll());
+            }
+        }
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult {
+        if character.is_control() {
+            return Ok(());
+        }
+        if let Some(text) = self.focused_input_text() {
+            text.push(character);
+        }
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> GameResult {
+        match input.keycode {
+            Some(KeyCode::Back) => {
+                if let Some(text) = self.focused_input_text() {
+                    text.pop();
+                }
+            }
+            Some(KeyCode::Return) | Some(KeyCode::Escape) => self.focused_input = None,
+            Some(KeyCode::F) if self.focused_input.is_none() => self.flipped = !self.flipped,
+            // the arrow keys step through a replayed game
+            Some(KeyCode::Left) if self.replay.is_some() && self.focused_input.is_none() => {
+                self.show_replay_ply(ctx, self.replay_ply.saturating_sub(1));
+            }
+            Some(KeyCode::Right) if self.replay.is_some() && self.focused_input.is_none() => {
+                self.show_replay_ply(ctx, self.replay_ply + 1);
+            }
+            Some(KeyCode::Home) if self.replay.is_some() && self.focused_input.is_none() => {
+                self.show_replay_ply(ctx, 0);
+            }
+            Some(KeyCode::End) if self.replay.is_some() && self.focused_input.is_none() => {
+                self.show_replay_ply(ctx, usize::MAX);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas: graphics::Canvas =
+            graphics::Canvas::from_frame(ctx, graphics::Color::from([0.1, 0.2, 0.3, 1.0]));
+        canvas.set_screen_coordinates(self.layout.visible);
+        let mouse = self
+            .layout
+            .to_design(ctx.mouse.position().x, ctx.mouse.position().y);
+        // Draw an image.
+        //canvas.draw(&self.image, graphics::DrawParam::new().dest(dst));
+        match self.phase {
+            Phase::Menu => self.draw_menu(&mut canvas),
+            Phase::Lobby => self.draw_lobby(&mut canvas, ctx)?,
+            Phase::InGame => self.draw_in_game(&mut canvas),
+            Phase::Disconnected => self.draw_disconnected(&mut canvas, ctx)?,
+            Phase::GameOver => self.draw_game_over(&mut canvas),
+        }
+        // display rank and file, in the order they are seen from the side at the bottom
+        for i in 0..8 {
+            let (rank_label, file_label) = if self.flipped {
+                (i + 1, b'h' - i as u8)
+            } else {
+                (8 - i, b'a' + i as u8)
+            };
+            // display rank
+            let rank_text = Text::new(rank_label.to_string());
+            rank_text.draw(
+                &mut canvas,
+                graphics::DrawParam::new().dest(glam::Vec2::new(
+                    BOARD_X - 20.0,
+                    BOARD_Y + 30.0 + ((i as f32) * SQUARE_SIZE),
+                )),
+            );
+            // display file
+            let file_text = Text::new((file_label as char).to_string());
+            file_text.draw(
+                &mut canvas,
+                graphics::DrawParam::new().dest(glam::Vec2::new(
+                    BOARD_X + 30.0 + ((i as f32) * SQUARE_SIZE),
+                    BOARD_Y + BOARD_SIZE + 10.0,
+                )),
+            );
+        }
+
+        if let Some(clock) = &self.clock {
+            draw_clocks(&mut canvas, ctx, clock)?;
+        }
+        if let Some(connection) = &self.connection {
+            draw_connection_quality(&mut canvas, ctx, connection)?;
+        }
+
+        if let Some((score, side, depth)) = self.engine_evaluation {
+            Text::new(format!(
+                "Evaluation: {}",
+                format_evaluation(score, side, depth)
+            ))
+            .draw(
+                &mut canvas,
+                graphics::DrawParam::new().dest(glam::Vec2::new(800.0, 300.0)),
+            );
+        }
+        if self.ai_search.is_some() {
+            Text::new("Computer is thinking…").draw(
+                &mut canvas,
+                graphics::DrawParam::new().dest(glam::Vec2::new(800.0, 270.0)),
+            );
+        }
+        for (field, input) in self.text_inputs() {
+            let text = match field {
+                TextInputField::Fen => &self.fen_input,
+                TextInputField::Address => &self.address_input,
+                TextInputField::EnginePath => &self.engine_path_input,
+                TextInputField::PgnPath => &self.pgn_path_input,
+                TextInputField::Timeout => &self.timeout_input,
+            };
+            input.draw(
+                &mut canvas,
                 ctx,
-                graphics::DrawMode::Fill(FillOptions::default()),
-                circle_position,
-                10.0,
-                1.0,
-                circle_color,
+                text,
+                self.focused_input == Some(field),
+                mouse,
             )?;
+        }
+        if let Some(error_message) = &self.error_message {
+            Text::new(error_message.as_str()).draw(
+                &mut canvas,
+                graphics::DrawParam::new()
+                    .dest(glam::Vec2::new(100.0, 1020.0))
+                    .color(graphics::Color::new(1.0, 0.3, 0.3, 1.0)),
+            );
+        }
+        if let Some(dispute) = &self.protocol_dispute {
+            Label::new(Vec2::new(100.0, 15.0), dispute.as_str())
+                .with_color(graphics::Color::new(1.0, 0.3, 0.3, 1.0))
+                .draw(&mut canvas);
+        }
+        // on its own row below the clocks, the evaluation and the connection indicator
+        if let Some(info_message) = &self.info_message {
+            Text::new(info_message.as_str()).draw(
+                &mut canvas,
+                graphics::DrawParam::new().dest(glam::Vec2::new(800.0, 360.0)),
+            );
+        }
 
-            let text = Text::new(piece_move.1.to_string());
+        // the move leading to the shown position is highlighted in the list
+        let shown_ply = match (&self.replay, self.viewing_ply) {
+            (Some(_), _) => Some(self.replay_ply),
+            (None, Some(ply)) => Some(ply),
+            (None, None) => None,
+        };
+        draw_move_list(
+            &mut canvas,
+            ctx,
+            self.start_fen.as_deref(),
+            &self.past_moves,
+            self.move_list_scroll,
+            shown_ply,
+        )?;
+        if let Some(ply) = self.viewing_ply {
+            Label::new(
+                glam::Vec2::new(MOVE_LIST_X, 750.0),
+                format!("Viewing move {} of {}", ply, self.past_moves.len()),
+            )
+            .draw(&mut canvas);
+        }
 
-            color_circle.draw(&mut canvas, graphics::DrawParam::default());
-            text.draw(
+        // highlights sit between the squares and the pieces
+        let shown_board = self.viewed_board.as_ref().unwrap_or(&self.board);
+        let shown_ply = self.viewing_ply.unwrap_or(self.past_moves.len());
+        if let Some(last_move) = shown_ply
+            .checked_sub(1)
+            .and_then(|index| self.past_moves.get(index))
+        {
+            for square in [last_move.chess_move.from(), last_move.chess_move.to()] {
+                draw_square_highlight(
+                    &mut canvas,
+                    ctx,
+                    square,
+                    self.flipped,
+                    Color::new(0.8, 0.8, 0.2, 0.45),
+                )?;
+            }
+        }
+        if let Some(square) = self
+            .selected_square
+            .filter(|_| self.current_legal_moves.is_some())
+        {
+            draw_square_highlight(
                 &mut canvas,
-                graphics::DrawParam::default().dest(text_position),
-            );
+                ctx,
+                square,
+                self.flipped,
+                Color::new(0.3, 0.6, 0.9, 0.6),
+            )?;
+        }
+        if is_in_check(shown_board) {
+            if let Some(square) = king_square(shown_board, shown_board.side()) {
+                draw_square_highlight(
+                    &mut canvas,
+                    ctx,
+                    square,
+                    self.flipped,
+                    Color::new(0.9, 0.1, 0.1, 0.6),
+                )?;
+            }
         }
 
         canvas.set_sampler(graphics::Sampler::nearest_clamp());
-        for (index, image) in self.piece_images.iter().enumerate() {
+        let shown_images = self.viewed_images.as_ref().unwrap_or(&self.piece_images);
+        let dragged = self.drag_square.filter(|_| self.drag_position.is_some());
+        for (index, image) in shown_images.iter().enumerate() {
             if image.is_some() {
                 let square = Square::from_idx(index as u8);
-                let x_pos: f32 = 110.0 + (80.0 * (square.file.to_idx() as f32));
-                let y_pos: f32 = 110.0 + (80.0 * (square.rank.to_idx() as f32));
-                let image_destination = glam::Vec2::new(x_pos, y_pos);
+                if dragged == Some(square) {
+                    continue;
+                }
+                let image_destination =
+                    square_position(square, self.flipped) + glam::Vec2::new(10.0, 10.0);
                 let piece_image = image.as_ref().unwrap();
 
                 canvas.draw(
@@ -659,21 +2393,50 @@ impl event::EventHandler<ggez::GameError> for State {
             }
         }
 
+        // the dragged piece follows the cursor above everything else on the board
+        if let (Some(square), Some(position)) = (dragged, self.drag_position) {
+            if let Some(piece_image) = &shown_images[square_to_index(square)] {
+                canvas.draw(
+                    piece_image,
+                    graphics::DrawParam::new()
+                        .dest(position - glam::Vec2::new(30.0, 30.0))
+                        .z(200),
+                );
+            }
+        }
+
         if self.current_legal_moves.is_some() {
             for legal_move in self.current_legal_moves.as_ref().unwrap() {
-                let new_position = Vec2::new(
-                    140.0 + (80.0 * (legal_move.to().file.to_idx() as f32)),
-                    140.0 + (80.0 * (legal_move.to().rank.to_idx() as f32)),
-                );
+                let new_position =
+                    square_position(legal_move.to(), self.flipped) + Vec2::new(40.0, 40.0);
 
-                let circle = graphics::Mesh::new_circle(
-                    ctx,
-                    graphics::DrawMode::Fill(FillOptions::default()),
-                    new_position,
-                    20.0,
-                    1.0,
-                    Color::new(0.658, 0.654, 0.639, 1.0),
-                )?;
+                // captures are shown as a ring around the piece instead of a dot, a pawn moving
+                // diagonally onto an empty square is taking en passant
+                let moving_pawn = self
+                    .board
+                    .get_piece(legal_move.from())
+                    .is_some_and(|p| p.1 == Piece::Pawn);
+                let is_capture = self.board.get_piece(legal_move.to()).is_some()
+                    || (moving_pawn && legal_move.from().file != legal_move.to().file);
+                let circle = if is_capture {
+                    graphics::Mesh::new_circle(
+                        ctx,
+                        graphics::DrawMode::stroke(6.0),
+                        new_position,
+                        36.0,
+                        1.0,
+                        Color::new(0.658, 0.654, 0.639, 1.0),
+                    )?
+                } else {
+                    graphics::Mesh::new_circle(
+                        ctx,
+                        graphics::DrawMode::Fill(FillOptions::default()),
+                        new_position,
+                        20.0,
+                        1.0,
+                        Color::new(0.658, 0.654, 0.639, 1.0),
+                    )?
+                };
                 canvas.draw(&circle, graphics::DrawParam::default().z(99));
             }
         }
@@ -690,6 +2453,13 @@ impl event::EventHandler<ggez::GameError> for State {
         }
         canvas.set_default_sampler();
 
+        for (_, button) in self.buttons() {
+            button.draw(&mut canvas, ctx, mouse)?;
+        }
+        if let Some(choices) = &self.promotion_choices {
+            draw_promotion_dialog(&mut canvas, ctx, choices)?;
+        }
+
         // Draw a stroked rectangle mesh.
         canvas.draw(&self.rect, graphics::DrawParam::default());
 
@@ -698,10 +2468,6 @@ impl event::EventHandler<ggez::GameError> for State {
         // Finished drawing, show it all on the screen!
         canvas.finish(ctx)?;
 
-        // function to handle the incoming or outgoing network packets
-
-        // Draw an image with some options, and different filter modes.
-        handle_incoming_packages(ctx, self);
         Ok(())
     }
 }
@@ -714,7 +2480,7 @@ fn main() -> GameResult {
     } else {
         path::PathBuf::from("./resources")
     };
-    let window_mode = WindowMode::default().dimensions(1200.0, 1200.0);
+    let window_mode = WindowMode::default().dimensions(layout::DESIGN_WIDTH, layout::DESIGN_HEIGHT);
     let cb = ggez::ContextBuilder::new("drawing", "ggez")
         .add_resource_path(resource_dir)
         .window_mode(window_mode.resizable(true));
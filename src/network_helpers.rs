@@ -1,321 +1,498 @@
-use crate::{piece_to_image, promotion_piece_to_piece, State};
+use crate::board_helpers::{opposite_color, square_name};
+use crate::framing::Packet;
+use crate::network_worker::{NetworkEvent, NetworkWorker, DEFAULT_SILENCE_TIMEOUT};
+use crate::san::move_to_san;
+use crate::uci::{move_to_uci, uci_to_move};
+use crate::{promotion_piece_to_piece, EndReason, Phase, State};
 use chess_networking::{Ack, GameState, Move as NetworkMove, PromotionPiece, Start};
 use dexterws_chess::game::{
-    Color as PieceColor, File, GameResult as ChessResult, Move, Piece, Rank, Square,
+    Color as PieceColor, File, GameResult as ChessResult, Move, Rank, Square,
 };
 use ggez::*;
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
-pub fn listen_for_connections(state: &mut State) {
-    let addrs = [
-        SocketAddr::from(([127, 0, 0, 1], 8080)),
-        SocketAddr::from(([127, 0, 0, 1], 8081)),
-    ];
-    let listener: TcpListener = TcpListener::bind(&addrs[..]).unwrap();
-    println!("Listening for connections");
-    listener.set_nonblocking(true).unwrap();
+// joining happens from the click handler, an unreachable host must not freeze the window for long
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+// how much time the opponent's clock may still show here when their forfeit for running out of
+// time arrives, the two clocks drift apart by about the network delay
+const FLAG_TOLERANCE: Duration = Duration::from_secs(1);
 
-    loop {
+pub fn listen_for_connections(address: String, state: &mut State) -> Result<(), String> {
+    // checked now rather than once the opponent has connected
+    state.silence_timeout()?;
+    let listener: TcpListener = TcpListener::bind(address.as_str())
+        .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+    println!("Listening for connections on {}", address);
+    // the listener is polled from update so the window keeps running while waiting
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+    state.listener = Some(listener);
+    Ok(())
+}
+
+// accepts the opponent once they have connected, called every frame while hosting
+pub(crate) fn poll_for_connection(state: &mut State) {
+    if let Some(listener) = &state.listener {
         match listener.accept() {
             Ok((stream, addr)) => {
                 println!("New connection: {}", addr);
-                stream
-                    .set_nonblocking(true)
-                    .expect("Failed to set client stream to non-blocking");
-                state.client_stream = Some(stream);
-                state.is_host = Some(true);
-                break;
+                state.listener = None;
+                let silence_timeout = state.silence_timeout().unwrap_or(DEFAULT_SILENCE_TIMEOUT);
+                match NetworkWorker::spawn(stream, silence_timeout) {
+                    Ok(worker) => {
+                        state.connection = Some(worker);
+                        state.is_host = Some(true);
+                    }
+                    Err(e) => {
+                        state.is_host = None;
+                        state.error_message = Some(format!("Could not accept {}: {}", addr, e));
+                    }
+                }
             }
             Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                // No connection available, continue looping
-                std::thread::sleep(std::time::Duration::from_millis(100));
+                // No connection available yet, try again next frame
             }
-            Err(e) => println!("Error accepting connection: {}", e),
+            Err(e) => {
+                // the listener can't be trusted anymore, hosting a new game goes back to the menu
+                // while a paused game can wait for the opponent again
+                println!("Error accepting connection: {}", e);
+                state.listener = None;
+                if state.phase == Phase::Lobby {
+                    state.leave_lobby();
+                }
+                state.error_message = Some(format!("Could not accept a connection: {}", e));
+            }
+        }
+    }
+}
+
+pub fn connect_to_host(address: String, state: &mut State) -> Result<(), String> {
+    let silence_timeout = state.silence_timeout()?;
+    let stream = connect_with_timeout(address.as_str())
+        .map_err(|e| format!("Could not connect to {}: {}", address, e))?;
+    println!("Connected to server: {}", stream.peer_addr().unwrap());
+    let worker = NetworkWorker::spawn(stream, silence_timeout)
+        .map_err(|e| format!("Could not connect to {}: {}", address, e))?;
+    state.connection = Some(worker);
+    state.is_host = Some(false);
+    Ok(())
+}
+
+// tries every address the host name resolves to, giving each one CONNECT_TIMEOUT
+fn connect_with_timeout(address: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, "No address found");
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
         }
     }
-    /* println!("Client accepted connection: {}", _addr);
-    handle_client(stream, state); */
+    Err(last_error)
 }
 
-pub fn connect_to_host(address: String, state: &mut State) {
-    match TcpStream::connect(address) {
-        Ok(mut stream) => {
-            println!("Connected to server: {}", stream.peer_addr().unwrap());
-            stream
-                .set_nonblocking(true)
-                .expect("Failed to set non-blocking");
-            state.client_stream = Some(stream);
-            state.is_host = Some(false);
+pub(crate) fn send_package(state: &mut State, packet: Packet) {
+    match state.connection.as_ref() {
+        Some(worker) => {
+            if let Err(e) = worker.send(packet) {
+                println!("Error sending package: {}", e);
+            }
         }
-        Err(e) => println!("Failed to connect: {}", e),
+        None => println!("No client stream found"),
     }
 }
 
+pub(crate) fn send_forfeit(state: &mut State) {
+    let forfeit_package = NetworkMove {
+        from: (0, 0),
+        to: (0, 0),
+        promotion: None,
+        forfeit: true,
+        offer_draw: false,
+    };
+    send_package(state, Packet::Move(forfeit_package));
+}
+
+// handles everything the network worker has received since the last frame
 pub fn handle_incoming_packages(ctx: &mut Context, state: &mut State) {
-    if let Some(stream) = &mut state.client_stream {
-        let mut buf = [0u8; 1024];
-        match stream.read(&mut buf) {
-            Ok(size) => {
-                if size > 0 {
-                    match NetworkMove::try_from(&buf[..]) {
-                        Ok(piece_move) => {
-                            if piece_move.forfeit {
-                                state.game_has_ended = true;
-                                state.client_stream = None;
-                                return;
-                            }
-                            if piece_move.offer_draw {
-                                state.offer_draw_received = true;
-                                return;
-                            }
+    let events = match state.connection.as_mut() {
+        Some(worker) => worker.poll(),
+        None => return,
+    };
+    for event in events {
+        // the connection is closed once the game has been decided
+        if state.connection.is_none() {
+            break;
+        }
+        match event {
+            NetworkEvent::Packet(Packet::Move(piece_move)) => handle_move(ctx, state, piece_move),
+            NetworkEvent::Packet(Packet::Ack(ack)) => handle_ack(ctx, state, ack),
+            NetworkEvent::Packet(Packet::Start(start)) => handle_start(ctx, state, start),
+            NetworkEvent::Packet(Packet::History(moves)) => handle_history(state, moves),
+            NetworkEvent::Packet(Packet::Rejection(reason)) => {
+                state.rejection_reason = Some(reason)
+            }
+            // keepalives are answered by the worker and never reach this far
+            NetworkEvent::Packet(Packet::Ping(_) | Packet::Pong(_)) => {}
+            NetworkEvent::Closed(reason) => state.connection_lost(reason),
+        }
+    }
+}
 
-                            let from = Square {
-                                file: File::from_idx(piece_move.from.0),
-                                rank: Rank::from_idx(piece_move.from.1),
-                            };
-                            let to = Square {
-                                file: File::from_idx(piece_move.to.0),
-                                rank: Rank::from_idx(piece_move.to.1),
-                            };
-                            let chess_move =
-                                Move::new(from, to, promotion_piece_to_piece(piece_move.promotion));
-                            if state.is_host.is_some_and(|f| f == false) {
-                                // client will always ack the move
-                                let return_move_package = Ack {
-                                    ok: true,
-                                    end_state: None,
-                                };
-                                let return_move_package_bytes: Vec<u8> =
-                                    return_move_package.try_into().unwrap();
-                                state
-                                    .client_stream
-                                    .as_ref()
-                                    .unwrap()
-                                    .write(&return_move_package_bytes)
-                                    .unwrap();
-                                match state.board.make_move(chess_move) {
-                                    Ok(_) => {
-                                        state
-                                            .past_moves
-                                            .insert(0, (state.board.side(), chess_move));
-                                        let pieces: [Option<(Piece, PieceColor)>; 64] =
-                                            state.board.get_all_pieces();
-                                        let piece_images: [Option<graphics::Image>; 64] = pieces
-                                            .map(|piece| match piece {
-                                                Some(p) => Some(
-                                                    graphics::Image::from_path(
-                                                        ctx,
-                                                        piece_to_image(p),
-                                                    )
-                                                    .unwrap(),
-                                                ),
-                                                None => None,
-                                            });
-                                        state.piece_images = piece_images;
-                                    }
-                                    Err(e) => println!("Error making move: {}", e),
-                                }
-                            }
-                            if state.is_host.is_some_and(|f| f == true) {
-                                let legal_moves = state.board.get_moves(chess_move.from());
-                                let is_legal = legal_moves.as_ref().unwrap().contains(&chess_move);
+fn handle_move(ctx: &mut Context, state: &mut State, piece_move: NetworkMove) {
+    if state.phase != Phase::InGame {
+        println!("Ignoring move outside of a game: {:?}", piece_move);
+        return;
+    }
+    if piece_move.forfeit {
+        if let Some(color) = state.player_color() {
+            // the forfeit comes from the opponent, one sent as their clock runs out means they
+            // lost on time
+            let opponent = opposite_color(color);
+            let out_of_time = state.clock.as_ref().is_some_and(|clock| {
+                clock.active() == opponent && clock.remaining(opponent) <= FLAG_TOLERANCE
+            });
+            state.end_game(if out_of_time {
+                EndReason::Timeout(opponent)
+            } else {
+                EndReason::Forfeit(opponent)
+            });
+        }
+        state.connection = None;
+        return;
+    }
+    if piece_move.offer_draw {
+        state.offer_draw_received = true;
+        return;
+    }
 
-                                if is_legal {
-                                    match state.board.make_move(chess_move) {
-                                        Ok(_) => {
-                                            let pieces: [Option<(Piece, PieceColor)>; 64] =
-                                                state.board.get_all_pieces();
-                                            let piece_images: [Option<graphics::Image>; 64] =
-                                                pieces.map(|piece| match piece {
-                                                    Some(p) => Some(
-                                                        graphics::Image::from_path(
-                                                            ctx,
-                                                            piece_to_image(p),
-                                                        )
-                                                        .unwrap(),
-                                                    ),
-                                                    None => None,
-                                                });
-                                            state.piece_images = piece_images;
+    // the coordinates come straight off the wire, a square outside the board can't be built
+    let on_board = |(file, rank): (u8, u8)| file < 8 && rank < 8;
+    if !on_board(piece_move.from) || !on_board(piece_move.to) {
+        reject_move(
+            state,
+            format!(
+                "Square outside of the board: {:?} to {:?}",
+                piece_move.from, piece_move.to
+            ),
+        );
+        return;
+    }
+    let from = Square {
+        file: File::from_idx(piece_move.from.0),
+        rank: Rank::from_idx(piece_move.from.1),
+    };
+    let to = Square {
+        file: File::from_idx(piece_move.to.0),
+        rank: Rank::from_idx(piece_move.to.1),
+    };
+    let chess_move = Move::new(from, to, promotion_piece_to_piece(piece_move.promotion));
+    // neither side takes the other's word for it, the move has to be theirs to make
+    let Some(opponent_color) = state.player_color().map(opposite_color) else {
+        reject_move(state, String::from("The game hasn't started"));
+        return;
+    };
+    if let Err(reason) = validate_move(state, opponent_color, chess_move) {
+        reject_move(state, reason);
+        return;
+    }
+    if state.is_host.is_some_and(|f| f == false) {
+        // client acks every move it can play, along with the result it sees after it
+        let return_move_package = match state.apply_move(ctx, chess_move) {
+            Ok(_) => Ack {
+                ok: true,
+                end_state: result_to_game_state(state.board.get_game_result()),
+            },
+            Err(e) => {
+                println!("Error making move: {}", e);
+                Ack {
+                    ok: false,
+                    end_state: None,
+                }
+            }
+        };
+        send_package(state, Packet::Ack(return_move_package));
+    }
+    if state.is_host.is_some_and(|f| f == true) {
+        if let Err(e) = state.apply_move(ctx, chess_move) {
+            println!("Error making move: {}", e);
+        }
+        let end_state = result_to_game_state(state.board.get_game_result());
 
-                                            state
-                                                .past_moves
-                                                .insert(0, (state.board.side(), chess_move));
-                                        }
-                                        Err(e) => println!("Error making move: {}", e),
-                                    }
-                                }
-                                let end_state =
-                                    if state.board.get_game_result() == ChessResult::InProgress {
-                                        None
-                                    } else if state.board.get_game_result()
-                                        == (ChessResult::Checkmate {
-                                            winner: PieceColor::White,
-                                        })
-                                        || state.board.get_game_result()
-                                            == (ChessResult::Checkmate {
-                                                winner: PieceColor::Black,
-                                            })
-                                    {
-                                        Some(GameState::CheckMate)
-                                    } else if state.board.get_game_result() == ChessResult::Draw {
-                                        Some(GameState::Draw)
-                                    } else {
-                                        None
-                                    };
+        let return_move_package = Ack {
+            ok: true,
+            end_state,
+        };
+        send_package(state, Packet::Ack(return_move_package));
+    }
+}
 
-                                let return_move_package = Ack {
-                                    ok: is_legal,
-                                    end_state,
-                                };
-                                let return_move_package_bytes: Vec<u8> =
-                                    return_move_package.try_into().unwrap();
-                                state
-                                    .client_stream
-                                    .as_ref()
-                                    .unwrap()
-                                    .write(&return_move_package_bytes)
-                                    .unwrap();
-                            }
-                        }
-                        Err(e) => println!("Error parsing move: {}", e),
-                    }
-                    match Ack::try_from(&buf[..]) {
-                        Ok(ack) => {
-                            if state.offer_draw_sent {
-                                if ack.ok {
-                                    state.game_has_ended = true;
-                                    state.client_stream = None;
-                                    state.offer_draw_sent = false;
-                                } else {
-                                    state.offer_draw_sent = false;
-                                }
-                                return;
-                            }
-                            if state.is_host.is_some_and(|f| f == false) {
-                                if ack.end_state.is_some() {
-                                    state.game_has_ended = true;
-                                    // Close the connection
+// the protocol only knows checkmate and draw, every way of drawing on the board counts as a draw
+fn result_to_game_state(result: ChessResult) -> Option<GameState> {
+    match result {
+        ChessResult::Checkmate { .. } => Some(GameState::CheckMate),
+        ChessResult::Draw
+        | ChessResult::Stalemate
+        | ChessResult::FiftyMoveRule
+        | ChessResult::ThreefoldRepetition => Some(GameState::Draw),
+        ChessResult::InProgress => None,
+    }
+}
 
-                                    state.client_stream = None;
-                                }
-                                if ack.ok {
-                                    match state.board.make_move(state.pending_chess_move.unwrap()) {
-                                        Ok(_) => {
-                                            state.past_moves.insert(
-                                                0,
-                                                (
-                                                    state.board.side(),
-                                                    state.pending_chess_move.unwrap(),
-                                                ),
-                                            );
-                                            state.pending_chess_move = None;
-                                            let pieces: [Option<(Piece, PieceColor)>; 64] =
-                                                state.board.get_all_pieces();
-                                            let piece_images: [Option<graphics::Image>; 64] =
-                                                pieces.map(|piece| match piece {
-                                                    Some(p) => Some(
-                                                        graphics::Image::from_path(
-                                                            ctx,
-                                                            piece_to_image(p),
-                                                        )
-                                                        .unwrap(),
-                                                    ),
-                                                    None => None,
-                                                });
-                                            state.piece_images = piece_images;
-                                        }
-                                        Err(e) => println!("Error making move: {}", e),
-                                    }
-                                }
-                            } else if state.is_host.is_some_and(|f| f == true) {
-                                match state.board.make_move(state.pending_chess_move.unwrap()) {
-                                    Ok(_) => {
-                                        state.past_moves.insert(
-                                            0,
-                                            (state.board.side(), state.pending_chess_move.unwrap()),
-                                        );
-                                        state.pending_chess_move = None;
-                                        let pieces: [Option<(Piece, PieceColor)>; 64] =
-                                            state.board.get_all_pieces();
-                                        let piece_images: [Option<graphics::Image>; 64] = pieces
-                                            .map(|piece| match piece {
-                                                Some(p) => Some(
-                                                    graphics::Image::from_path(
-                                                        ctx,
-                                                        piece_to_image(p),
-                                                    )
-                                                    .unwrap(),
-                                                ),
-                                                None => None,
-                                            });
-                                        state.piece_images = piece_images;
-                                    }
-                                    Err(e) => println!("Error making move: {}", e),
-                                }
-                            }
-                        }
-                        Err(e) => println!("Error parsing ack: {}", e),
-                    }
-                    match Start::try_from(&buf[..]) {
-                        Ok(start) => {
-                            println!("Received start: {:?}", start);
-                            if state.is_host.is_some_and(|f| f == true) {
-                                // selected color will always remain the same for the host
-                                // however if client has chosen same as host, then client color will be opposite
-                                let client_is_white = if start.is_white
-                                    && state.selected_color.is_some_and(|f| f == PieceColor::White)
-                                {
-                                    false
-                                } else if !start.is_white
-                                    && state.selected_color.is_some_and(|f| f == PieceColor::Black)
-                                {
-                                    true
-                                } else {
-                                    start.is_white
-                                };
-                                let return_start_package = Start {
-                                    is_white: client_is_white,
-                                    name: start.name,
-                                    fen: start.fen,
-                                    time: start.time,
-                                    inc: start.inc,
-                                };
-                                let return_start_package_bytes: Vec<u8> =
-                                    return_start_package.try_into().unwrap();
+fn describe_end_state(end_state: &Option<GameState>) -> &'static str {
+    match end_state {
+        Some(GameState::CheckMate) => "checkmate",
+        Some(GameState::Draw) => "a draw",
+        None => "still going",
+    }
+}
 
-                                state.start = Some(Start {
-                                    is_white: state.selected_color.unwrap() == PieceColor::White,
-                                    name: None,
-                                    fen: None,
-                                    time: None,
-                                    inc: None,
-                                });
-                                state
-                                    .client_stream
-                                    .as_ref()
-                                    .unwrap()
-                                    .write(&return_start_package_bytes)
-                                    .unwrap();
-                            } else {
-                                // client receives start package from host (after sending it once)
-                                state.selected_color = Some(if start.is_white {
-                                    PieceColor::Black
-                                } else {
-                                    PieceColor::White
-                                });
-                                state.start = Some(start);
-                            }
-                        }
-                        Err(e) => println!("Error parsing start: {}", e),
-                    }
+// each side works the result out on its own board, a peer claiming something else is flagged
+// rather than believed
+fn verify_end_state(state: &mut State, claimed: Option<GameState>) {
+    let own = result_to_game_state(state.board.get_game_result());
+    let agree = matches!(
+        (&claimed, &own),
+        (None, None)
+            | (Some(GameState::CheckMate), Some(GameState::CheckMate))
+            | (Some(GameState::Draw), Some(GameState::Draw))
+    );
+    if !agree {
+        let dispute = format!(
+            "Protocol dispute: the opponent says the game is {}, this board says {}",
+            describe_end_state(&claimed),
+            describe_end_state(&own)
+        );
+        println!("{}", dispute);
+        state.protocol_dispute = Some(dispute);
+    }
+}
+
+// checks a move `mover` wants to play against the board, anything that can't be played is refused
+// with a reason
+fn validate_move(state: &State, mover: PieceColor, chess_move: Move) -> Result<(), String> {
+    if state.board.side() != mover {
+        return Err(String::from("It is not your turn"));
+    }
+    let from = square_name(chess_move.from());
+    match state.board.get_piece(chess_move.from()) {
+        None => return Err(format!("There is no piece on {}", from)),
+        Some((color, _)) if color != mover => {
+            return Err(format!("The piece on {} isn't yours", from))
+        }
+        Some(_) => {}
+    }
+    let legal_moves = state.board.get_moves(chess_move.from()).unwrap_or_default();
+    if !legal_moves.contains(&chess_move) {
+        return Err(format!("{} is not a legal move", move_to_uci(&chess_move)));
+    }
+    Ok(())
+}
+
+// refuses the peer's move, the host also tells the client why
+fn reject_move(state: &mut State, reason: String) {
+    println!("Rejected move: {}", reason);
+    if state.is_host == Some(true) {
+        send_package(state, Packet::Rejection(reason));
+    }
+    let return_move_package = Ack {
+        ok: false,
+        end_state: None,
+    };
+    send_package(state, Packet::Ack(return_move_package));
+}
+
+fn handle_ack(ctx: &mut Context, state: &mut State, ack: Ack) {
+    if state.offer_draw_sent {
+        if ack.ok {
+            state.end_game(EndReason::DrawAgreed);
+            state.connection = None;
+            state.offer_draw_sent = false;
+        } else {
+            state.offer_draw_sent = false;
+        }
+        return;
+    }
+    if state.is_host.is_some_and(|f| f == false) {
+        let Some(pending_chess_move) = state.pending_chess_move.take() else {
+            println!("Received an ack without a pending move");
+            return;
+        };
+        if ack.ok {
+            // a refusal shown for an earlier attempt no longer applies
+            state.rejection_reason = None;
+            state.error_message = None;
+            if let Err(e) = state.apply_move(ctx, pending_chess_move) {
+                println!("Error making move: {}", e);
+            }
+            verify_end_state(state, ack.end_state);
+            // Close the connection once the game is over
+            if state.phase == Phase::GameOver {
+                state.connection = None;
+            }
+        } else {
+            // the move was never played here, so dropping it is all the rolling back there is
+            let reason = state
+                .rejection_reason
+                .take()
+                .unwrap_or_else(|| String::from("no reason given"));
+            state.error_message = Some(format!(
+                "The host refused {}: {}",
+                move_to_san(&state.board, pending_chess_move),
+                reason
+            ));
+        }
+    } else if state.is_host.is_some_and(|f| f == true) {
+        match state.pending_chess_move.take() {
+            Some(pending_chess_move) if ack.ok => {
+                if let Err(e) = state.apply_move(ctx, pending_chess_move) {
+                    println!("Error making move: {}", e);
                 }
+                verify_end_state(state, ack.end_state);
             }
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                // No data available right now, just continue
+            Some(pending_chess_move) => {
+                state.error_message = Some(format!(
+                    "The opponent refused {}",
+                    move_to_san(&state.board, pending_chess_move)
+                ));
             }
-            Err(e) => println!("Error reading from stream: {}", e),
+            None => println!("Received an ack without a pending move"),
+        }
+    }
+}
+
+fn handle_start(ctx: &mut Context, state: &mut State, start: Start) {
+    println!("Received start: {:?}", start);
+    // the start package begins a game in the lobby, or resumes one that was paused
+    if state.phase != Phase::Lobby && state.phase != Phase::Disconnected {
+        println!("Ignoring start package outside of the lobby");
+        return;
+    }
+    if state.is_host == Some(true) && state.phase == Phase::Disconnected {
+        resume_game(state, start);
+    } else if state.is_host.is_some_and(|f| f == true) {
+        // selected color will always remain the same for the host
+        // however if client has chosen same as host, then client color will be opposite
+        let client_is_white = if start.is_white
+            && state.selected_color.is_some_and(|f| f == PieceColor::White)
+        {
+            false
+        } else if !start.is_white && state.selected_color.is_some_and(|f| f == PieceColor::Black) {
+            true
+        } else {
+            start.is_white
+        };
+        // fall back to the standard position if the client sent a FEN we can't use
+        let fen = match state.reset_board(ctx, start.fen.clone()) {
+            Ok(_) => start.fen,
+            Err(e) => {
+                println!("{}, starting from standard position", e);
+                state.reset_board(ctx, None).unwrap();
+                None
+            }
+        };
+        state.opponent_name = start.name.clone();
+        let return_start_package = Start {
+            is_white: client_is_white,
+            name: start.name,
+            fen: fen.clone(),
+            time: start.time,
+            inc: start.inc,
+        };
+
+        state.start_clock(start.time, start.inc);
+        state.start = Some(Start {
+            is_white: state.selected_color.unwrap() == PieceColor::White,
+            name: None,
+            fen,
+            time: start.time,
+            inc: start.inc,
+        });
+        state.phase = Phase::InGame;
+        state.orient_board();
+        send_package(state, Packet::Start(return_start_package));
+    } else {
+        // client receives start package from host (after sending it once)
+        state.selected_color = Some(if start.is_white {
+            PieceColor::Black
+        } else {
+            PieceColor::White
+        });
+        let resuming = state.phase == Phase::Disconnected;
+        // a paused game keeps its clock, reset_board would throw it away
+        let paused_clock = state.clock.take();
+        if let Err(e) = state.reset_board(ctx, start.fen.clone()) {
+            println!("{}, starting from standard position", e);
+            state.reset_board(ctx, None).unwrap();
+        }
+        // moves are only played in a game, including the ones of a resumed game's history
+        state.phase = Phase::InGame;
+        // the host sends the moves of a resumed game just before its start package
+        if let Some(history) = state.resume_history.take() {
+            replay_history(ctx, state, &history);
+        }
+        if resuming && paused_clock.is_some() {
+            state.clock = paused_clock;
+        } else {
+            state.start_clock(start.time, start.inc);
+        }
+        state.start = Some(start);
+        if resuming {
+            state.connection_restored();
+        }
+        state.orient_board();
+    }
+}
+
+// the opponent has come back to a paused game, whatever it asked for it keeps its color and gets
+// the game's starting position and every move since, which together pin down castling and en
+// passant rights that a fen of the current position alone would have to rebuild
+fn resume_game(state: &mut State, start: Start) {
+    let Some((is_white, fen, time, inc)) = state
+        .start
+        .as_ref()
+        .map(|own| (own.is_white, own.fen.clone(), own.time, own.inc))
+    else {
+        return;
+    };
+    if start.name.is_some() {
+        state.opponent_name = start.name.clone();
+    }
+    let history = state.move_history().iter().map(move_to_uci).collect();
+    send_package(state, Packet::History(history));
+    let return_start_package = Start {
+        is_white: !is_white,
+        name: start.name,
+        fen,
+        time,
+        inc,
+    };
+    send_package(state, Packet::Start(return_start_package));
+    state.connection_restored();
+}
+
+fn handle_history(state: &mut State, moves: Vec<String>) {
+    if state.is_host == Some(true)
+        || (state.phase != Phase::Lobby && state.phase != Phase::Disconnected)
+    {
+        println!("Ignoring move history");
+        return;
+    }
+    state.resume_history = Some(moves);
+}
+
+// plays the moves of a resumed game on the freshly set up board
+fn replay_history(ctx: &mut Context, state: &mut State, history: &[String]) {
+    for uci in history {
+        let Some(chess_move) = uci_to_move(&state.board, uci) else {
+            println!("Illegal move in the history: {}", uci);
+            return;
+        };
+        if let Err(e) = state.apply_move(ctx, chess_move) {
+            println!("Error making move: {}", e);
+            return;
         }
     }
 }
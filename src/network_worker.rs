@@ -0,0 +1,197 @@
+use crate::framing::{FramedStream, Packet};
+use std::io;
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how long the worker waits for incoming data before checking for packets to send
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// how often the peer is pinged, the pings also tell it that we are still here
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+// how long the peer may stay silent before the connection is given up, unless set in the ui
+pub const DEFAULT_SILENCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub enum NetworkEvent {
+    Packet(Packet),
+    // the worker has stopped, either because the peer closed the connection or because of an error
+    Closed(String),
+}
+
+// what the worker thread reports, pings are handled on the thread and only their timing comes back
+enum WorkerMessage {
+    Event(NetworkEvent),
+    RoundTrip(Duration),
+}
+
+// owns the socket on its own thread so slow peers never stall rendering, the gui only talks to it
+// through channels
+pub struct NetworkWorker {
+    outgoing: Sender<Packet>,
+    incoming: Receiver<WorkerMessage>,
+    // round trip time of the latest answered ping
+    round_trip: Option<Duration>,
+    // when the latest ping was answered
+    last_answer: Instant,
+}
+
+impl NetworkWorker {
+    pub fn spawn(stream: TcpStream, silence_timeout: Duration) -> io::Result<NetworkWorker> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_nodelay(true)?;
+
+        let (outgoing, outgoing_receiver) = channel();
+        let (incoming_sender, incoming) = channel();
+        thread::spawn(move || {
+            run(
+                FramedStream::new(stream),
+                outgoing_receiver,
+                incoming_sender,
+                silence_timeout,
+            )
+        });
+
+        Ok(NetworkWorker {
+            outgoing,
+            incoming,
+            round_trip: None,
+            last_answer: Instant::now(),
+        })
+    }
+
+    pub fn send(&self, packet: Packet) -> Result<(), String> {
+        self.outgoing
+            .send(packet)
+            .map_err(|_| String::from("Network worker has stopped"))
+    }
+
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.round_trip
+    }
+
+    // how long it has been since the peer last answered a ping
+    pub fn silent_for(&self) -> Duration {
+        self.last_answer.elapsed()
+    }
+
+    // everything that has arrived since the last call, in order
+    pub fn poll(&mut self) -> Vec<NetworkEvent> {
+        let mut events = vec![];
+        loop {
+            match self.incoming.try_recv() {
+                Ok(WorkerMessage::Event(event)) => events.push(event),
+                Ok(WorkerMessage::RoundTrip(round_trip)) => {
+                    self.round_trip = Some(round_trip);
+                    self.last_answer = Instant::now();
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !events.iter().any(|e| matches!(e, NetworkEvent::Closed(_))) {
+                        events.push(NetworkEvent::Closed(String::from(
+                            "Network worker has stopped",
+                        )));
+                    }
+                    break;
+                }
+            }
+        }
+        events
+    }
+}
+
+fn report_closed(incoming: &Sender<WorkerMessage>, reason: String) {
+    let _ = incoming.send(WorkerMessage::Event(NetworkEvent::Closed(reason)));
+}
+
+fn run(
+    mut stream: FramedStream,
+    outgoing: Receiver<Packet>,
+    incoming: Sender<WorkerMessage>,
+    silence_timeout: Duration,
+) {
+    // pings carry the milliseconds since this instant, so the answer tells how long it took
+    let started = Instant::now();
+    let mut last_ping: Option<Instant> = None;
+    let mut last_heard = Instant::now();
+    loop {
+        if last_ping.is_none_or(|sent| sent.elapsed() >= KEEPALIVE_INTERVAL) {
+            last_ping = Some(Instant::now());
+            if let Err(e) = stream.send(Packet::Ping(started.elapsed().as_millis() as u64)) {
+                report_closed(&incoming, format!("Error sending ping: {}", e));
+                return;
+            }
+        }
+        loop {
+            match outgoing.try_recv() {
+                Ok(packet) => {
+                    if let Err(e) = stream.send(packet) {
+                        report_closed(&incoming, format!("Error sending package: {}", e));
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                // the gui dropped the worker, dropping the stream here closes the connection
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if let Err(e) = stream.flush_pending() {
+            report_closed(&incoming, format!("Error writing to stream: {}", e));
+            return;
+        }
+
+        match stream.receive() {
+            Ok(packets) => {
+                if !packets.is_empty() {
+                    last_heard = Instant::now();
+                }
+                for packet in packets {
+                    match packet {
+                        Packet::Ping(sent) => {
+                            if let Err(e) = stream.send(Packet::Pong(sent)) {
+                                report_closed(&incoming, format!("Error sending ping: {}", e));
+                                return;
+                            }
+                        }
+                        Packet::Pong(sent) => {
+                            let round_trip = started
+                                .elapsed()
+                                .saturating_sub(Duration::from_millis(sent));
+                            if incoming.send(WorkerMessage::RoundTrip(round_trip)).is_err() {
+                                return;
+                            }
+                        }
+                        packet => {
+                            if incoming
+                                .send(WorkerMessage::Event(NetworkEvent::Packet(packet)))
+                                .is_err()
+                            {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                report_closed(&incoming, format!("Error reading from stream: {}", e));
+                return;
+            }
+        }
+        if stream.is_closed() {
+            report_closed(&incoming, String::from("Connection closed by peer"));
+            return;
+        }
+        // a peer that doesn't even answer pings is as good as gone
+        if last_heard.elapsed() > silence_timeout {
+            report_closed(
+                &incoming,
+                format!(
+                    "No answer from the opponent for {} seconds",
+                    silence_timeout.as_secs()
+                ),
+            );
+            return;
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use crate::board_from_fen;
+use crate::san::{first_move_number, moves_to_san, san_to_move};
+use dexterws_chess::game::{Board, Move};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// export lines are wrapped before this many characters, as the pgn standard asks
+const MAX_LINE_LEN: usize = 79;
+
+// the seven tag roster, every exported game has these tags in this order
+pub struct PgnHeaders {
+    pub event: String,
+    pub site: String,
+    pub date: String,
+    pub round: String,
+    pub white: String,
+    pub black: String,
+    pub result: String,
+}
+
+impl PgnHeaders {
+    fn tags(&self) -> [(&str, &str); 7] {
+        [
+            ("Event", &self.event),
+            ("Site", &self.site),
+            ("Date", &self.date),
+            ("Round", &self.round),
+            ("White", &self.white),
+            ("Black", &self.black),
+            ("Result", &self.result),
+        ]
+    }
+}
+
+// one move of an imported game together with its annotations
+pub struct PgnMove {
+    pub chess_move: Move,
+    pub san: String,
+    // numeric annotation glyphs, move suffixes like ! and ?? are stored as their glyph numbers
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+}
+
+pub struct PgnGame {
+    // tag pairs in the order they appear in the file
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>,
+}
+
+impl PgnGame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // the position the game starts from, None = standard position
+    pub fn start_fen(&self) -> Option<&str> {
+        self.header("FEN")
+    }
+}
+
+fn suffix_to_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+// how an annotation glyph is shown next to its move
+pub fn nag_to_string(nag: u8) -> String {
+    match nag {
+        1 => String::from("!"),
+        2 => String::from("?"),
+        3 => String::from("!!"),
+        4 => String::from("??"),
+        5 => String::from("!?"),
+        6 => String::from("?!"),
+        _ => format!(" ${}", nag),
+    }
+}
+
+fn is_result(token: &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+// reads the first game of a pgn file, moves are checked against the rules while parsing and
+// variations are skipped
+pub fn parse_pgn(text: &str) -> Result<PgnGame, String> {
+    // lines starting with % are escaped and ignored
+    let text: String = text
+        .lines()
+        .filter(|line| !line.starts_with('%'))
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let mut chars = text.chars().peekable();
+    let mut headers = vec![];
+    let mut moves: Vec<PgnMove> = vec![];
+    let mut board: Option<Board> = None;
+    // nesting depth of the variation being skipped
+    let mut variation_depth = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '[' if variation_depth == 0 => {
+                // a tag after the movetext belongs to the next game
+                if board.is_some() {
+                    break;
+                }
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                    name.push(c);
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if chars.next() != Some('"') {
+                    return Err(format!("Tag {} has no value", name));
+                }
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => value.extend(chars.next()),
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("Tag {} is not closed", name)),
+                    }
+                }
+                while chars.next_if(|c| *c != ']').is_some() {}
+                chars.next();
+                headers.push((name, value));
+            }
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                if variation_depth == 0 {
+                    add_comment(&mut moves, &comment);
+                }
+            }
+            ';' => {
+                let comment: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                if variation_depth == 0 {
+                    add_comment(&mut moves, &comment);
+                }
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth = (variation_depth - 1).max(0),
+            '$' => {
+                let mut number = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+                    number.push(c);
+                }
+                if variation_depth == 0 {
+                    if let (Some(last), Ok(nag)) = (moves.last_mut(), number.parse()) {
+                        last.nags.push(nag);
+                    }
+                }
+            }
+            c => {
+                let mut token = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"{}();[$".contains(*c))
+                {
+                    token.push(c);
+                }
+                if variation_depth > 0 {
+                    continue;
+                }
+                if is_result(&token) {
+                    break;
+                }
+                let san = strip_move_number(&token);
+                if san.is_empty() {
+                    continue;
+                }
+                // the movetext has started, so all of the tags are known
+                if board.is_none() {
+                    let start_fen = headers
+                        .iter()
+                        .find(|(tag, _)| tag == "FEN")
+                        .map(|(_, value)| value.as_str());
+                    board = Some(board_from_fen(start_fen)?);
+                }
+                let board = board.as_mut().unwrap();
+                let suffix_start = san.find(['!', '?']).unwrap_or(san.len());
+                let (san, suffix) = san.split_at(suffix_start);
+                let chess_move = san_to_move(board, san)?;
+                board.make_move(chess_move).map_err(|e| e.to_string())?;
+                moves.push(PgnMove {
+                    chess_move,
+                    san: san.to_string(),
+                    nags: suffix_to_nag(suffix).into_iter().collect(),
+                    comment: None,
+                });
+            }
+        }
+    }
+    if headers.is_empty() && moves.is_empty() {
+        return Err(String::from("No game found in the file"));
+    }
+    Ok(PgnGame { headers, moves })
+}
+
+// move numbers like 12. or 12... may be written right against the move, only digits followed
+// by a dot are one, so castling written as 0-0 is left alone
+fn strip_move_number(token: &str) -> &str {
+    let rest = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+// comments belong to the move they follow, a comment before the first move is dropped
+fn add_comment(moves: &mut [PgnMove], comment: &str) {
+    let comment = comment.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if let Some(last) = moves.last_mut() {
+        last.comment = Some(match last.comment.take() {
+            Some(previous) => format!("{} {}", previous, comment),
+            None => comment,
+        });
+    }
+}
+
+pub fn load_pgn(path: &str) -> Result<PgnGame, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path, e))?;
+    parse_pgn(&text)
+}
+
+// today's date in the pgn format, YYYY.MM.DD
+pub fn pgn_date_today() -> String {
+    let days = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => (elapsed.as_secs() / 86_400) as i64,
+        Err(_) => return String::from("????.??.??"),
+    };
+    // days since 1970-01-01 to a civil date, from Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// a complete pgn game, moves are given oldest first starting from start_fen or the standard position
+pub fn export_pgn(
+    headers: &PgnHeaders,
+    start_fen: Option<&str>,
+    moves: &[Move],
+) -> Result<String, String> {
+    let mut pgn = String::new();
+    for (name, value) in headers.tags() {
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+    }
+    if let Some(fen) = start_fen {
+        pgn.push_str("[SetUp \"1\"]\n");
+        pgn.push_str(&format!("[FEN \"{}\"]\n", escape_tag_value(fen)));
+    }
+    pgn.push('\n');
+
+    let start = board_from_fen(start_fen)?;
+    let (mut number, mut white_to_move) = first_move_number(start_fen);
+    let mut tokens = vec![];
+    for (i, san) in moves_to_san(&start, moves).into_iter().enumerate() {
+        if white_to_move {
+            tokens.push(format!("{}.", number));
+        } else if i == 0 {
+            tokens.push(format!("{}...", number));
+        }
+        tokens.push(san);
+        if !white_to_move {
+            number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+    tokens.push(headers.result.clone());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LEN {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    Ok(pgn)
+}
+
+// writes the game to the working directory and returns the file name
+pub fn save_pgn(pgn: &str) -> Result<String, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let file_name = format!("game_{}.pgn", timestamp);
+    fs::write(&file_name, pgn).map_err(|e| format!("Error saving {}: {}", file_name, e))?;
+    Ok(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uci::move_to_uci;
+
+    fn sans(game: &PgnGame) -> Vec<&str> {
+        game.moves.iter().map(|m| m.san.as_str()).collect()
+    }
+
+    fn ucis(game: &PgnGame) -> Vec<String> {
+        game.moves
+            .iter()
+            .map(|m| move_to_uci(&m.chess_move))
+            .collect()
+    }
+
+    #[test]
+    fn strips_move_numbers_but_not_castling() {
+        assert_eq!(strip_move_number("12.e4"), "e4");
+        assert_eq!(strip_move_number("12...Nf6"), "Nf6");
+        assert_eq!(strip_move_number("12."), "");
+        assert_eq!(strip_move_number("e4"), "e4");
+        assert_eq!(strip_move_number("0-0"), "0-0");
+        assert_eq!(strip_move_number("0-0-0"), "0-0-0");
+    }
+
+    #[test]
+    fn reads_move_numbers_joined_to_moves() {
+        let game = parse_pgn(
+            "[Event \"Casual\"]\n\
+             [FEN \"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 12\"]\n\
+             \n\
+             12.e4 12...Nf6 13.e5 Nd5 *",
+        )
+        .unwrap();
+        assert_eq!(game.header("Event"), Some("Casual"));
+        assert_eq!(sans(&game), ["e4", "Nf6", "e5", "Nd5"]);
+        assert_eq!(ucis(&game), ["e2e4", "g8f6", "e4e5", "f6d5"]);
+    }
+
+    #[test]
+    fn reads_castling_written_with_zeros_from_a_fen() {
+        let fen = "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1";
+        let game = parse_pgn(&format!(
+            "[SetUp \"1\"]\n[FEN \"{}\"]\n\n1. 0-0 0-0-0 *",
+            fen
+        ))
+        .unwrap();
+        assert_eq!(game.start_fen(), Some(fen));
+        assert_eq!(ucis(&game), ["e1g1", "e8c8"]);
+    }
+
+    #[test]
+    fn keeps_comments_nags_and_suffixes() {
+        let game = parse_pgn(
+            "1. e4! {best by test} e5 $6 2. Nf3?? ; a line comment\nNc6 { two } { parts } *",
+        )
+        .unwrap();
+        assert_eq!(sans(&game), ["e4", "e5", "Nf3", "Nc6"]);
+        assert_eq!(game.moves[0].nags, [1]);
+        assert_eq!(game.moves[0].comment.as_deref(), Some("best by test"));
+        assert_eq!(game.moves[1].nags, [6]);
+        assert_eq!(game.moves[2].nags, [4]);
+        assert_eq!(game.moves[2].comment.as_deref(), Some("a line comment"));
+        assert_eq!(game.moves[3].comment.as_deref(), Some("two parts"));
+    }
+
+    #[test]
+    fn skips_nested_variations() {
+        let game =
+            parse_pgn("1. e4 (1. d4 d5 (1... Nf6 2. c4 {inside}) 2. c4) e5 2. Nf3 *").unwrap();
+        assert_eq!(sans(&game), ["e4", "e5", "Nf3"]);
+        assert!(game.moves.iter().all(|m| m.comment.is_none()));
+    }
+
+    #[test]
+    fn stops_at_an_illegal_move() {
+        assert_eq!(
+            parse_pgn("1. e4 e5 2. Ke3 *").err(),
+            Some(String::from("Illegal move: Ke3"))
+        );
+    }
+
+    #[test]
+    fn numbers_a_game_starting_with_black() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 b - - 0 7";
+        let board = crate::board_from_fen(Some(fen)).unwrap();
+        let king_move = san_to_move(&board, "Kd7").unwrap();
+        let mut after = board.clone();
+        after.make_move(king_move).unwrap();
+        let rook_move = san_to_move(&after, "Ra7").unwrap();
+        let headers = PgnHeaders {
+            event: String::from("Casual"),
+            site: String::from("?"),
+            date: String::from("2024.01.01"),
+            round: String::from("-"),
+            white: String::from("White"),
+            black: String::from("Black"),
+            result: String::from("*"),
+        };
+        let pgn = export_pgn(&headers, Some(fen), &[king_move, rook_move]).unwrap();
+        assert!(pgn.ends_with(&format!("[FEN \"{}\"]\n\n7... Kd7 8. Ra7+ *\n", fen)));
+
+        // and reads back as the same game
+        let game = parse_pgn(&pgn).unwrap();
+        assert_eq!(game.header("Date"), Some("2024.01.01"));
+        assert_eq!(sans(&game), ["Kd7", "Ra7+"]);
+        assert_eq!(ucis(&game), ["e8d7", "a1a7"]);
+    }
+}
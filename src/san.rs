@@ -0,0 +1,278 @@
+use crate::board_helpers::{all_legal_moves, is_in_check, square_name, square_to_index};
+use dexterws_chess::game::{Board, GameResult as ChessResult, Move, Piece};
+
+pub fn piece_letter(piece: Piece) -> Option<char> {
+    match piece {
+        Piece::Pawn => None,
+        Piece::Knight => Some('N'),
+        Piece::Bishop => Some('B'),
+        Piece::Rook => Some('R'),
+        Piece::Queen => Some('Q'),
+        Piece::King => Some('K'),
+    }
+}
+
+// standard algebraic notation for a legal move, `board` is the position before the move is made
+pub fn move_to_san(board: &Board, chess_move: Move) -> String {
+    let pieces = board.get_all_pieces();
+    let from = chess_move.from();
+    let to = chess_move.to();
+    let Some((piece, _)) = pieces[square_to_index(from)] else {
+        return square_name(from) + &square_name(to);
+    };
+    let file_distance = to.file.to_idx() as i8 - from.file.to_idx() as i8;
+
+    let mut san = if piece == Piece::King && file_distance.abs() == 2 {
+        String::from(if file_distance > 0 { "O-O" } else { "O-O-O" })
+    } else {
+        // a pawn changing file is always a capture, even when the square it lands on is empty
+        let is_capture =
+            pieces[square_to_index(to)].is_some() || (piece == Piece::Pawn && file_distance != 0);
+        let mut san = String::new();
+        match piece_letter(piece) {
+            Some(letter) => {
+                san.push(letter);
+                san.push_str(&disambiguation(board, chess_move, piece));
+            }
+            None => {
+                if is_capture {
+                    san.push((b'a' + from.file.to_idx()) as char);
+                }
+            }
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(to));
+        if let Some(letter) = chess_move.promotion().and_then(piece_letter) {
+            san.push('=');
+            san.push(letter);
+        }
+        san
+    };
+
+    let mut after = board.clone();
+    if after.make_move(chess_move).is_ok() {
+        if matches!(after.get_game_result(), ChessResult::Checkmate { .. }) {
+            san.push('#');
+        } else if is_in_check(&after) {
+            san.push('+');
+        }
+    }
+    san
+}
+
+// the file, rank or both of the starting square when another piece of the same kind could also
+// reach the target square
+fn disambiguation(board: &Board, chess_move: Move, piece: Piece) -> String {
+    let pieces = board.get_all_pieces();
+    let from = chess_move.from();
+    let rivals: Vec<Move> = all_legal_moves(board)
+        .into_iter()
+        .filter(|m| {
+            m.to() == chess_move.to()
+                && m.from() != from
+                && pieces[square_to_index(m.from())].is_some_and(|(p, _)| p == piece)
+        })
+        .collect();
+    if rivals.is_empty() {
+        return String::new();
+    }
+    let file = (b'a' + from.file.to_idx()) as char;
+    let rank = (b'1' + from.rank.to_idx()) as char;
+    if rivals.iter().all(|m| m.from().file != from.file) {
+        file.to_string()
+    } else if rivals.iter().all(|m| m.from().rank != from.rank) {
+        rank.to_string()
+    } else {
+        format!("{}{}", file, rank)
+    }
+}
+
+// the move number and whether white moves first, read from the fen's last fields
+pub fn first_move_number(start_fen: Option<&str>) -> (u32, bool) {
+    let Some(fen) = start_fen else {
+        return (1, true);
+    };
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let white_to_move = fields.get(1) != Some(&"b");
+    let number = fields.get(5).and_then(|n| n.parse().ok()).unwrap_or(1);
+    (number, white_to_move)
+}
+
+// the move list as numbered rows of white's and black's move, a game starting with black to
+// move leaves the first white move empty
+pub fn move_rows(
+    first_number: u32,
+    white_first: bool,
+    sans: &[String],
+) -> Vec<(u32, Option<&str>, Option<&str>)> {
+    let mut rows = vec![];
+    let mut sans = sans.iter().map(String::as_str);
+    let mut number = first_number;
+    if !white_first {
+        match sans.next() {
+            Some(black) => rows.push((number, None, Some(black))),
+            None => return rows,
+        }
+        number += 1;
+    }
+    while let Some(white) = sans.next() {
+        rows.push((number, Some(white), sans.next()));
+        number += 1;
+    }
+    rows
+}
+
+// san for every move of a game, replayed from its starting position
+pub fn moves_to_san(start: &Board, moves: &[Move]) -> Vec<String> {
+    let mut board = start.clone();
+    let mut sans = vec![];
+    for chess_move in moves {
+        sans.push(move_to_san(&board, *chess_move));
+        if board.make_move(*chess_move).is_err() {
+            break;
+        }
+    }
+    sans
+}
+
+fn letter_piece(letter: char) -> Option<Piece> {
+    match letter {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+// the legal move a san string describes, check marks, annotations and redundant
+// disambiguation are accepted
+pub fn san_to_move(board: &Board, san: &str) -> Result<Move, String> {
+    let text = san.trim_end_matches(['+', '#', '!', '?']);
+    let pieces = board.get_all_pieces();
+    let legal_moves = all_legal_moves(board);
+    let piece_at = |m: &Move| pieces[square_to_index(m.from())].map(|(piece, _)| piece);
+
+    let candidates: Vec<Move> = if matches!(text, "O-O" | "0-0" | "O-O-O" | "0-0-0") {
+        let kingside = text.len() == 3;
+        legal_moves
+            .into_iter()
+            .filter(|m| {
+                let distance = m.to().file.to_idx() as i8 - m.from().file.to_idx() as i8;
+                piece_at(m) == Some(Piece::King) && distance == if kingside { 2 } else { -2 }
+            })
+            .collect()
+    } else {
+        let mut chars: Vec<char> = text.chars().filter(|c| *c != 'x' && *c != '-').collect();
+        let piece = match chars.first().copied().and_then(letter_piece) {
+            Some(piece) => {
+                chars.remove(0);
+                piece
+            }
+            None => Piece::Pawn,
+        };
+        // both e8=Q and e8Q are in use
+        let promotion = match chars.last().copied().and_then(letter_piece) {
+            Some(promotion) => {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+                Some(promotion)
+            }
+            None => None,
+        };
+        if chars.len() < 2 {
+            return Err(format!("Invalid move: {}", san));
+        }
+        let target: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        // whatever is left of the starting square, a file, a rank or both
+        let from_file = chars.iter().find(|c| ('a'..='h').contains(*c));
+        let from_rank = chars.iter().find(|c| ('1'..='8').contains(*c));
+        legal_moves
+            .into_iter()
+            .filter(|m| {
+                piece_at(m) == Some(piece)
+                    && square_name(m.to()) == target
+                    && m.promotion() == promotion
+                    && from_file.is_none_or(|f| square_name(m.from()).starts_with(*f))
+                    && from_rank.is_none_or(|r| square_name(m.from()).ends_with(*r))
+            })
+            .collect()
+    };
+    match candidates.as_slice() {
+        [chess_move] => Ok(*chess_move),
+        [] => Err(format!("Illegal move: {}", san)),
+        _ => Err(format!("Ambiguous move: {}", san)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_from_fen;
+    use crate::uci::uci_to_move;
+
+    // the san of a move given in uci notation, which has to read back as the same move
+    fn round_trip(fen: &str, uci: &str) -> String {
+        let board = board_from_fen(Some(fen)).unwrap();
+        let chess_move = uci_to_move(&board, uci).unwrap();
+        let san = move_to_san(&board, chess_move);
+        assert_eq!(san_to_move(&board, &san), Ok(chess_move));
+        san
+    }
+
+    #[test]
+    fn disambiguates_by_file() {
+        let fen = "4k3/8/8/8/8/8/8/1N3N1K w - - 0 1";
+        assert_eq!(round_trip(fen, "b1d2"), "Nbd2");
+        assert_eq!(round_trip(fen, "f1d2"), "Nfd2");
+        assert_eq!(round_trip(fen, "f1g3"), "Ng3");
+    }
+
+    #[test]
+    fn disambiguates_by_rank() {
+        let fen = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(round_trip(fen, "a1a3"), "R1a3");
+        assert_eq!(round_trip(fen, "a5a3"), "R5a3");
+    }
+
+    #[test]
+    fn disambiguates_by_file_and_rank() {
+        let fen = "7k/8/8/1N3N2/8/1N6/8/7K w - - 0 1";
+        assert_eq!(round_trip(fen, "b5d4"), "Nb5d4");
+        assert_eq!(round_trip(fen, "b3d4"), "N3d4");
+        assert_eq!(round_trip(fen, "f5d4"), "Nfd4");
+    }
+
+    #[test]
+    fn marks_check_and_mate() {
+        assert_eq!(round_trip("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+        assert_eq!(
+            round_trip("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"),
+            "Ra8#"
+        );
+    }
+
+    #[test]
+    fn writes_promotions() {
+        let fen = "1r6/P6k/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(round_trip(fen, "a7a8q"), "a8=Q");
+        assert_eq!(round_trip(fen, "a7a8n"), "a8=N");
+        assert_eq!(round_trip(fen, "a7b8r"), "axb8=R");
+
+        // the equals sign is optional when reading
+        let board = board_from_fen(Some(fen)).unwrap();
+        assert_eq!(san_to_move(&board, "a8Q"), san_to_move(&board, "a8=Q"));
+    }
+
+    #[test]
+    fn writes_castling() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(round_trip(fen, "e1g1"), "O-O");
+        assert_eq!(round_trip(fen, "e1c1"), "O-O-O");
+    }
+}
@@ -0,0 +1,372 @@
+use crate::board_helpers::all_legal_moves;
+use dexterws_chess::game::{Board, Move, Piece};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::thread;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UciScore {
+    Centipawns(i32),
+    // moves until mate, negative when the side to move is getting mated
+    Mate(i32),
+}
+
+#[derive(PartialEq, Debug)]
+pub enum UciEvent {
+    // the engine's move in coordinate notation, e.g. e7e8q
+    BestMove(String),
+    // score of the position from the point of view of the side to move
+    Info { depth: Option<u32>, score: UciScore },
+    // the engine process has exited or closed its output
+    Closed,
+}
+
+// drives a uci engine process, lines from the engine are read on their own thread so polling
+// never blocks the ui
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    // a stopped search still answers with a bestmove, so the info lines that come before that
+    // answer belong to the old position
+    searches_in_flight: usize,
+}
+
+impl UciEngine {
+    pub fn spawn(path: &str) -> io::Result<UciEngine> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            child,
+            stdin,
+            lines,
+            searches_in_flight: 0,
+        };
+        // the engine handles commands in order, so there is no need to wait for uciok here
+        engine.send("uci")?;
+        engine.send("isready")?;
+        engine.send("ucinewgame")?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.searches_in_flight > 0
+    }
+
+    // moves are given oldest first, starting from start_fen or the standard position
+    pub fn set_position(&mut self, start_fen: Option<&str>, moves: &[Move]) -> io::Result<()> {
+        self.send(&position_command(start_fen, moves))
+    }
+
+    pub fn go_movetime(&mut self, millis: u64) -> io::Result<()> {
+        self.searches_in_flight += 1;
+        self.send(&format!("go movetime {}", millis))
+    }
+
+    pub fn go_infinite(&mut self) -> io::Result<()> {
+        self.searches_in_flight += 1;
+        self.send("go infinite")
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        if self.is_searching() {
+            self.send("stop")?;
+        }
+        Ok(())
+    }
+
+    // everything the engine has said since the last call that is about the current search
+    pub fn poll(&mut self) -> Vec<UciEvent> {
+        let mut events = vec![];
+        loop {
+            match self.lines.try_recv() {
+                Ok(line) => match parse_line(&line) {
+                    Some(UciEvent::BestMove(best_move)) => {
+                        self.searches_in_flight = self.searches_in_flight.saturating_sub(1);
+                        if self.searches_in_flight == 0 {
+                            events.push(UciEvent::BestMove(best_move));
+                        }
+                    }
+                    Some(info @ UciEvent::Info { .. }) => {
+                        if self.searches_in_flight == 1 {
+                            events.push(info);
+                        }
+                    }
+                    Some(UciEvent::Closed) | None => {}
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    events.push(UciEvent::Closed);
+                    break;
+                }
+            }
+        }
+        events
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+pub fn move_to_uci(chess_move: &Move) -> String {
+    let mut uci = format!(
+        "{}{}{}{}",
+        (b'a' + chess_move.from().file.to_idx()) as char,
+        (b'1' + chess_move.from().rank.to_idx()) as char,
+        (b'a' + chess_move.to().file.to_idx()) as char,
+        (b'1' + chess_move.to().rank.to_idx()) as char,
+    );
+    match chess_move.promotion() {
+        Some(Piece::Queen) => uci.push('q'),
+        Some(Piece::Rook) => uci.push('r'),
+        Some(Piece::Bishop) => uci.push('b'),
+        Some(Piece::Knight) => uci.push('n'),
+        _ => {}
+    }
+    uci
+}
+
+// the legal move in this position that matches the engine's coordinate notation
+pub fn uci_to_move(board: &Board, uci: &str) -> Option<Move> {
+    all_legal_moves(board)
+        .into_iter()
+        .find(|m| move_to_uci(m) == uci)
+}
+
+pub fn position_command(start_fen: Option<&str>, moves: &[Move]) -> String {
+    let mut command = match start_fen {
+        Some(fen) => format!("position fen {}", fen),
+        None => String::from("position startpos"),
+    };
+    if !moves.is_empty() {
+        command.push_str(" moves");
+        for chess_move in moves {
+            command.push(' ');
+            command.push_str(&move_to_uci(chess_move));
+        }
+    }
+    command
+}
+
+pub fn parse_line(line: &str) -> Option<UciEvent> {
+    let mut tokens = line.split_whitespace();
+    match tokens.next()? {
+        "bestmove" => tokens
+            .next()
+            .map(|best_move| UciEvent::BestMove(best_move.to_string())),
+        "info" => {
+            let mut depth = None;
+            let mut score = None;
+            while let Some(token) = tokens.next() {
+                match token {
+                    "depth" => depth = tokens.next().and_then(|d| d.parse().ok()),
+                    "score" => {
+                        score = match (tokens.next(), tokens.next().and_then(|s| s.parse().ok())) {
+                            (Some("cp"), Some(value)) => Some(UciScore::Centipawns(value)),
+                            (Some("mate"), Some(value)) => Some(UciScore::Mate(value)),
+                            _ => None,
+                        }
+                    }
+                    // the principal variation runs to the end of the line
+                    "pv" => break,
+                    _ => {}
+                }
+            }
+            score.map(|score| UciEvent::Info { depth, score })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dexterws_chess::game::{File, Rank, Square};
+    use std::time::{Duration, Instant};
+
+    fn square(name: &str) -> Square {
+        let bytes = name.as_bytes();
+        Square {
+            file: File::from_idx(bytes[0] - b'a'),
+            rank: Rank::from_idx(bytes[1] - b'1'),
+        }
+    }
+
+    #[test]
+    fn parses_centipawn_score_and_depth() {
+        assert_eq!(
+            parse_line("info depth 12 seldepth 18 score cp 34 nodes 1000 pv e2e4 e7e5"),
+            Some(UciEvent::Info {
+                depth: Some(12),
+                score: UciScore::Centipawns(34),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_mate_score() {
+        assert_eq!(
+            parse_line("info score mate -3 depth 20"),
+            Some(UciEvent::Info {
+                depth: Some(20),
+                score: UciScore::Mate(-3),
+            })
+        );
+    }
+
+    #[test]
+    fn stops_reading_at_the_principal_variation() {
+        // "depth" after pv is a move list token as far as the engine is concerned
+        assert_eq!(
+            parse_line("info score cp 10 pv e2e4 depth 99"),
+            Some(UciEvent::Info {
+                depth: None,
+                score: UciScore::Centipawns(10),
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_info_without_a_score() {
+        assert_eq!(parse_line("info depth 5 nodes 1234"), None);
+        assert_eq!(parse_line("info string hello"), None);
+        assert_eq!(parse_line("readyok"), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn parses_bestmove() {
+        assert_eq!(
+            parse_line("bestmove e7e8q ponder a2a3"),
+            Some(UciEvent::BestMove(String::from("e7e8q")))
+        );
+        assert_eq!(parse_line("bestmove"), None);
+    }
+
+    #[test]
+    fn position_from_the_start() {
+        assert_eq!(position_command(None, &[]), "position startpos");
+        let moves = [
+            Move::new(square("e2"), square("e4"), None),
+            Move::new(square("e7"), square("e5"), None),
+        ];
+        assert_eq!(
+            position_command(None, &moves),
+            "position startpos moves e2e4 e7e5"
+        );
+    }
+
+    #[test]
+    fn position_from_a_fen() {
+        let fen = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            position_command(Some(fen), &[]),
+            format!("position fen {}", fen)
+        );
+        let moves = [Move::new(square("a7"), square("a8"), Some(Piece::Queen))];
+        assert_eq!(
+            position_command(Some(fen), &moves),
+            format!("position fen {} moves a7a8q", fen)
+        );
+    }
+
+    // an engine that answers every go with one info line scored by how many searches it has
+    // started, and only answers with a bestmove when told to stop
+    #[cfg(unix)]
+    fn spawn_fake_engine(name: &str) -> UciEngine {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("{}_{}.sh", name, std::process::id()));
+        std::fs::write(
+            &path,
+            "#!/bin/sh\n\
+             searches=0\n\
+             while read line; do\n\
+             case \"$line\" in\n\
+             go*) searches=$((searches + 1)); echo \"info depth 1 score cp $searches\" ;;\n\
+             stop) echo \"bestmove e2e4\" ;;\n\
+             quit) exit 0 ;;\n\
+             esac\n\
+             done\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        UciEngine::spawn(path.to_str().unwrap()).unwrap()
+    }
+
+    // polls until `done` is satisfied by everything received so far, or gives up after a while
+    #[cfg(unix)]
+    fn poll_until(engine: &mut UciEngine, done: impl Fn(&[UciEvent]) -> bool) -> Vec<UciEvent> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut events = vec![];
+        while !done(&events) && Instant::now() < deadline {
+            events.extend(engine.poll());
+            thread::sleep(Duration::from_millis(10));
+        }
+        events
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drops_lines_from_stopped_searches() {
+        let mut engine = spawn_fake_engine("fake_engine_stale");
+        engine.go_infinite().unwrap();
+        engine.stop().unwrap();
+        engine.go_infinite().unwrap();
+        assert!(engine.is_searching());
+
+        // the first search's info and bestmove are both stale by the time they are read
+        let events = poll_until(&mut engine, |events| !events.is_empty());
+        assert_eq!(
+            events,
+            vec![UciEvent::Info {
+                depth: Some(1),
+                score: UciScore::Centipawns(2),
+            }]
+        );
+
+        engine.stop().unwrap();
+        let events = poll_until(&mut engine, |events| !events.is_empty());
+        assert_eq!(events, vec![UciEvent::BestMove(String::from("e2e4"))]);
+        assert!(!engine.is_searching());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_a_closed_engine() {
+        let mut engine = spawn_fake_engine("fake_engine_closed");
+        engine.send("quit").unwrap();
+        let events = poll_until(&mut engine, |events| events.contains(&UciEvent::Closed));
+        assert_eq!(events, vec![UciEvent::Closed]);
+    }
+}
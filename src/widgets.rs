@@ -0,0 +1,227 @@
+use ggez::glam::Vec2;
+use ggez::graphics::{self, Canvas, Color, DrawParam, Drawable, Rect, Text};
+use ggez::{Context, GameResult};
+
+// widgets own the rectangle they are drawn in, so drawing and hit-testing can't disagree
+
+fn lighten(color: Color, amount: f32) -> Color {
+    Color::new(
+        color.r + (1.0 - color.r) * amount,
+        color.g + (1.0 - color.g) * amount,
+        color.b + (1.0 - color.b) * amount,
+        color.a,
+    )
+}
+
+pub struct Button {
+    pub rect: Rect,
+    pub label: String,
+    pub color: Color,
+    pub text_color: Color,
+    // a disabled button is drawn greyed out and ignores clicks
+    pub enabled: bool,
+    // buttons on top of the board, like the ones in dialogs, need to be above the pieces
+    pub z: i32,
+}
+
+impl Button {
+    pub fn new(rect: Rect, label: impl Into<String>) -> Button {
+        Button {
+            rect,
+            label: label.into(),
+            color: Color::new(1.0, 0.0, 0.0, 1.0),
+            text_color: Color::WHITE,
+            enabled: true,
+            z: 0,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Button {
+        self.color = color;
+        self
+    }
+
+    pub fn with_text_color(mut self, text_color: Color) -> Button {
+        self.text_color = text_color;
+        self
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Button {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn with_z(mut self, z: i32) -> Button {
+        self.z = z;
+        self
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.rect.contains(point)
+    }
+
+    pub fn is_clicked(&self, point: Vec2) -> bool {
+        self.enabled && self.contains(point)
+    }
+
+    // `mouse` is the cursor position, the button lights up while it is hovered
+    pub fn draw(&self, canvas: &mut Canvas, ctx: &mut Context, mouse: Vec2) -> GameResult {
+        let (color, text_color) = if !self.enabled {
+            (
+                Color::new(0.3, 0.3, 0.3, 1.0),
+                Color::new(0.6, 0.6, 0.6, 1.0),
+            )
+        } else if self.contains(mouse) {
+            (lighten(self.color, 0.3), self.text_color)
+        } else {
+            (self.color, self.text_color)
+        };
+        let background = graphics::Mesh::new_rounded_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            self.rect,
+            5.0,
+            color,
+        )?;
+        background.draw(canvas, DrawParam::default().z(self.z));
+
+        // the label is centred in the button
+        let text = Text::new(self.label.as_str());
+        let size = text.measure(ctx)?;
+        text.draw(
+            canvas,
+            DrawParam::default()
+                .dest(Vec2::new(
+                    self.rect.x + (self.rect.w - size.x) / 2.0,
+                    self.rect.y + (self.rect.h - size.y) / 2.0,
+                ))
+                .color(text_color)
+                .z(self.z + 1),
+        );
+        Ok(())
+    }
+}
+
+pub struct Label {
+    pub position: Vec2,
+    pub text: String,
+    pub color: Color,
+    pub z: i32,
+}
+
+impl Label {
+    pub fn new(position: Vec2, text: impl Into<String>) -> Label {
+        Label {
+            position,
+            text: text.into(),
+            color: Color::WHITE,
+            z: 0,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Label {
+        self.color = color;
+        self
+    }
+
+    pub fn with_z(mut self, z: i32) -> Label {
+        self.z = z;
+        self
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas) {
+        Text::new(self.text.as_str()).draw(
+            canvas,
+            DrawParam::default()
+                .dest(self.position)
+                .color(self.color)
+                .z(self.z),
+        );
+    }
+}
+
+// a single line text field, the text itself lives in the state and is passed in when drawing
+pub struct TextInput {
+    pub rect: Rect,
+    pub label: String,
+}
+
+impl TextInput {
+    pub fn new(rect: Rect, label: impl Into<String>) -> TextInput {
+        TextInput {
+            rect,
+            label: label.into(),
+        }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.rect.contains(point)
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas,
+        ctx: &mut Context,
+        text: &str,
+        focused: bool,
+        mouse: Vec2,
+    ) -> GameResult {
+        let color = if focused {
+            Color::new(1.0, 1.0, 1.0, 1.0)
+        } else if self.contains(mouse) {
+            Color::new(0.9, 0.9, 0.9, 1.0)
+        } else {
+            Color::new(0.8, 0.8, 0.8, 1.0)
+        };
+        let background = graphics::Mesh::new_rounded_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            self.rect,
+            5.0,
+            color,
+        )?;
+        Label::new(
+            Vec2::new(self.rect.x, self.rect.y - 20.0),
+            self.label.as_str(),
+        )
+        .draw(canvas);
+        background.draw(canvas, DrawParam::default());
+        Label::new(
+            Vec2::new(self.rect.x + 5.0, self.rect.y + 7.0),
+            if focused {
+                format!("{}|", text)
+            } else {
+                text.to_string()
+            },
+        )
+        .with_color(Color::BLACK)
+        .with_z(100)
+        .draw(canvas);
+        Ok(())
+    }
+}
+
+// a plain filled rectangle that other widgets are placed on, like the dimmed board behind a dialog
+pub struct Panel {
+    pub rect: Rect,
+    pub color: Color,
+    pub z: i32,
+}
+
+impl Panel {
+    pub fn new(rect: Rect, color: Color) -> Panel {
+        Panel { rect, color, z: 0 }
+    }
+
+    pub fn with_z(mut self, z: i32) -> Panel {
+        self.z = z;
+        self
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas, ctx: &mut Context) -> GameResult {
+        let mesh =
+            graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), self.rect, self.color)?;
+        mesh.draw(canvas, DrawParam::default().z(self.z));
+        Ok(())
+    }
+}